@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Write `contents` to `path`, see [`write_with`]
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_with(path, |file| file.write_all(contents))
+}
+
+/// Write a file through a temporary file next to it, which replaces `path` once `fill` has
+/// written it completely. Readers and interrupted runs never see a partial file. Parent
+/// directories are created as needed.
+pub fn write_with<F>(path: &Path, fill: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp_path = temp_path(path);
+    let written = File::create(&temp_path).and_then(|mut file| {
+        fill(&mut file)?;
+        file.sync_all()
+    });
+    match written {
+        Ok(()) => std::fs::rename(&temp_path, path),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Temporary file `path` is written to before it replaces it
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = path.as_os_str().to_os_string();
+    temp_path.push(".tmp");
+    PathBuf::from(temp_path)
+}
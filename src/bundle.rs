@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use super::atomic_file;
+use super::manifest::{Location, Manifest, Origin};
+use super::signature::{TrustedKeys, SIGNATURE_EXTENSION};
+
+/// Name of the manifest inside a bundle
+pub const MANIFEST_ENTRY: &str = "manifest.json";
+/// Directory inside a bundle holding the files, named by hash
+const FILES_DIR: &str = "files";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Location of a file inside the bundle archive
+pub struct BundleEntry {
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+/// A tar archive holding a manifest and the files needed to apply it, for offline installs.
+///
+/// Files are stored uncompressed at `files/<hash>`, so they can be streamed straight from
+/// their offset in the archive.
+pub struct Bundle {
+    pub path: PathBuf,
+    entries: HashMap<String, BundleEntry>,
+}
+
+impl Bundle {
+    /// Open a bundle and index its entries
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut archive = tar::Archive::new(std::fs::File::open(path)?);
+        let mut entries = HashMap::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            entries.insert(
+                name,
+                BundleEntry {
+                    offset: entry.raw_file_position(),
+                    size: entry.size(),
+                },
+            );
+        }
+        if !entries.contains_key(MANIFEST_ENTRY) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not a patch bundle, it has no manifest",
+                    path.display()
+                ),
+            ));
+        }
+        Ok(Bundle {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Read the bundled manifest, checking its signature like a manifest file
+    pub fn manifest(&self, trust: &TrustedKeys) -> Result<Manifest, Box<dyn Error>> {
+        let contents = String::from_utf8(self.read(MANIFEST_ENTRY)?)?;
+        let signature = match self.read(&format!("{MANIFEST_ENTRY}{SIGNATURE_EXTENSION}")) {
+            Ok(signature) => Some(String::from_utf8(signature)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let status = trust.verify(contents.as_bytes(), signature.as_deref())?;
+        let mut manifest = Manifest::from_json(&contents)?;
+        manifest.origin = Origin {
+            location: Some(Location::FilePath(self.path.clone())),
+            signature: status,
+            contents: Some(contents),
+            raw_signature: signature,
+            ..Default::default()
+        };
+        Ok(manifest)
+    }
+
+    /// Location of the file with the given hash
+    pub fn file(&self, hash: &str) -> Option<BundleEntry> {
+        self.entries
+            .get(&format!("{FILES_DIR}/{}", hash.to_lowercase()))
+            .copied()
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entries.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{name} is not in the bundle"),
+            )
+        })?;
+        let mut file = std::fs::File::open(&self.path)?;
+        io::Seek::seek(&mut file, io::SeekFrom::Start(entry.offset))?;
+        let mut contents = Vec::new();
+        file.take(entry.size).read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Write a bundle with the manifest, its signature if any, and files given as hash and path.
+    ///
+    /// The archive is written next to `path` first and renamed once complete.
+    pub fn write(
+        path: &Path,
+        manifest: &str,
+        signature: Option<&str>,
+        files: &[(String, PathBuf)],
+    ) -> io::Result<()> {
+        atomic_file::write_with(path, |file| {
+            let mut builder = tar::Builder::new(file);
+            append_bytes(&mut builder, MANIFEST_ENTRY, manifest.as_bytes())?;
+            if let Some(signature) = signature {
+                append_bytes(
+                    &mut builder,
+                    &format!("{MANIFEST_ENTRY}{SIGNATURE_EXTENSION}"),
+                    signature.as_bytes(),
+                )?;
+            }
+            let mut written = std::collections::HashSet::new();
+            for (hash, file) in files {
+                let hash = hash.to_lowercase();
+                if written.insert(hash.clone()) {
+                    builder.append_path_with_name(file, format!("{FILES_DIR}/{hash}"))?;
+                }
+            }
+            builder.into_inner()?;
+            Ok(())
+        })
+    }
+}
+
+fn append_bytes(
+    builder: &mut tar::Builder<&mut std::fs::File>,
+    name: &str,
+    contents: &[u8],
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, contents)
+}
@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::manifest::Location;
+
+/// Default manifest URL template. `{channel}` is replaced with the channel key.
+pub const DEFAULT_MANIFEST_TEMPLATE: &str =
+    "https://updater.project-epoch.net/api/v2/manifest?environment={channel}";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Release channel served by the manifest endpoint
+pub enum Channel {
+    Production,
+    Staging,
+    Ptr,
+    #[serde(untagged)]
+    Custom(String),
+}
+
+impl Channel {
+    /// Get the channel key as used in the manifest URL and on the CLI
+    pub fn key(&self) -> &str {
+        match self {
+            Channel::Production => "production",
+            Channel::Staging => "staging",
+            Channel::Ptr => "ptr",
+            Channel::Custom(name) => name,
+        }
+    }
+
+    /// Build the manifest location for this channel from a URL template containing `{channel}`
+    pub fn manifest_location(&self, template: &str) -> Result<Location, &'static str> {
+        if !template.contains("{channel}") {
+            return Err("Manifest template must contain a {channel} placeholder");
+        }
+        Location::parse(template.replace("{channel}", self.key()))
+    }
+}
+
+impl FromStr for Channel {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "production" => Channel::Production,
+            "staging" => Channel::Staging,
+            "ptr" => Channel::Ptr,
+            other => {
+                // Custom channels end up in a URL, keep them to a safe character set
+                if other.is_empty()
+                    || !other
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return Err("Channel name may only contain letters, digits, '-' and '_'");
+                }
+                Channel::Custom(other.to_string())
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key())
+    }
+}
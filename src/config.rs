@@ -1,34 +1,597 @@
-use clap::{arg, Command};
+use clap::{arg, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use url::Url;
 
+use super::channel::{Channel, DEFAULT_MANIFEST_TEMPLATE};
+use super::download_cache::DownloadCache;
+use super::game;
+use super::http::{self, HttpConfig};
+use super::ignore::IgnoreRules;
 use super::manifest::{Location, Provider};
+use super::policy::{ModifiedPolicy, PolicyRules};
+use super::probe::ProviderChoice;
+use super::profile::{Profile, ProfileRegistry};
+use super::signature::TrustedKeys;
+use super::transaction::{CustomMode, MAX_SEGMENTS};
+
+/// What the patcher should do after parsing the command line
+#[derive(Debug)]
+pub enum Action {
+    /// Patch the installation described by the top-level options
+    Patch,
+    /// Remove custom content and patch the stock client files
+    Uninstall,
+    /// Write the files needed to patch the installation into a bundle, or every file with `all`
+    Export { path: PathBuf, all: bool },
+    /// Download every file of the manifest into a directory and write a manifest pointing at
+    /// the directory served from `base_url`
+    Mirror {
+        dir: PathBuf,
+        base_url: Url,
+        signing_key: Option<PathBuf>,
+    },
+    /// Serve the verified files of the installation over HTTP, with the manifest pointing at
+    /// `base_url` or the host each client connected to
+    Serve {
+        bind: SocketAddr,
+        base_url: Option<Url>,
+        signing_key: Option<PathBuf>,
+    },
+    /// Print the providers offered by the manifest
+    Providers,
+    /// Print the recorded state of the installation
+    Status,
+    /// Print all registered profiles
+    ProfileList,
+    /// Register a profile, optionally replacing an existing one with the same name
+    ProfileAdd { profile: Profile, replace: bool },
+    /// Remove a profile by name
+    ProfileRemove(String),
+    /// Patch the named profiles, or every profile if the list is empty
+    ProfileUpdate(Vec<String>),
+    /// Print the entries of the download cache
+    CacheList,
+    /// Remove download cache entries that do not match their hash
+    CacheVerify,
+    /// Remove every download cache entry
+    CachePurge,
+}
 
 #[derive(Debug)]
 pub struct Config {
-    pub manifest_location: Location,
-    pub manifest_provider: Provider,
+    /// Manifest locations in order of preference
+    pub manifest_locations: Vec<Location>,
+    pub manifest_provider: ProviderChoice,
+    /// Channel the manifest location was built from, `None` if `--manifest` was given
+    pub channel: Option<Channel>,
+    pub base_path: PathBuf,
+    pub profiles_path: PathBuf,
+    pub assume_yes: bool,
+    /// Hash every file even if the manifest was already applied
+    pub full_verify: bool,
+    pub http: HttpConfig,
+    pub trust: TrustedKeys,
+    /// Detached signature of a manifest read from stdin
+    pub stdin_signature: Option<PathBuf>,
+    /// Forget the newest manifest seen from the manifest sources before loading the manifest
+    pub reset_manifest_baseline: bool,
+    /// Remember the selected provider for future runs against the same installation
+    pub remember_provider: bool,
+    /// How to handle files the user changed since they were installed
+    pub modified_policy: PolicyRules,
+    /// Include/exclude rules given on the command line, applied after the ignore file
+    pub ignore: IgnoreRules,
+    /// How to treat files marked as custom server content
+    pub custom: CustomMode,
+    /// Optional content groups to add to the remembered selection
+    pub groups: Vec<String>,
+    /// Optional content groups to remove from the remembered selection
+    pub without_groups: Vec<String>,
+    /// Client locale, detected from the game directory if `None`
+    pub locale: Option<String>,
+    /// Hard link local files with matching contents instead of copying them
+    pub hard_link: bool,
+    /// Download cache shared between installations, if enabled
+    pub cache: Option<DownloadCache>,
+    /// Offline bundle to patch from instead of the manifest sources
+    pub bundle: Option<PathBuf>,
+    /// Connections used to download a large file
+    pub segments: usize,
+    /// Size in bytes from which files are downloaded over several connections
+    pub segment_threshold: u64,
+    pub action: Action,
+}
+
+/// Directory holding patcher-wide configuration such as the profile registry.
+///
+/// Uses `%APPDATA%` on Windows and `$XDG_CONFIG_HOME` or `~/.config` elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let dir = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    dir.map(|dir| dir.join("rs_manifest_patcher"))
+}
+
+/// Directory inside a game installation where the patcher keeps its own data
+pub fn data_dir(base_path: &std::path::Path) -> PathBuf {
+    base_path.join(".patcher")
+}
+
+fn manifest_args() -> [Arg; 3] {
+    [
+        arg!(-m --manifest <String> "Path to manifest.json file or URL (e.g., http://localhost:8080/manifest.json). Overrides --channel. Repeat to add fallback sources, tried in order")
+            .action(ArgAction::Append),
+        arg!(-c --channel <CHANNEL> "Release channel: production, staging, ptr or a custom name")
+            .default_value("production")
+            .conflicts_with("manifest"),
+        arg!(--"manifest-template" <URL> "Manifest URL template used with --channel, {channel} is replaced by the channel name")
+            .default_value(DEFAULT_MANIFEST_TEMPLATE)
+            .conflicts_with("manifest"),
+    ]
+}
+
+/// Resolve the manifest locations from `--manifest`, or from `--channel` and the URL template
+fn parse_manifest(matches: &ArgMatches) -> Result<(Vec<Location>, Option<Channel>), &'static str> {
+    if let Some(manifest_strs) = matches.get_many::<String>("manifest") {
+        let locations = manifest_strs
+            .map(|manifest_str| Location::parse(manifest_str.to_string()))
+            .collect::<Result<_, _>>()?;
+        return Ok((locations, None));
+    }
+    let channel = Channel::from_str(matches.get_one::<String>("channel").unwrap())?;
+    let template = matches.get_one::<String>("manifest-template").unwrap();
+    Ok((vec![channel.manifest_location(template)?], Some(channel)))
+}
+
+fn provider_arg() -> Arg {
+    arg!(-p --provider <String> "Provider to use for downloads")
+        .help("Provider to use for downloads, e.g. cloudflare (Server #1), digitalocean (Server #2), none (Server #3 - Slowest), or auto to pick the fastest. Run the `providers` command to list every provider offered by the manifest. Defaults to the remembered provider, or cloudflare")
+}
+
+fn parse_provider(matches: &ArgMatches) -> Option<ProviderChoice> {
+    matches
+        .get_one::<String>("provider")
+        .map(|provider_str| ProviderChoice::from_str(provider_str).unwrap())
+}
+
+/// Arguments extending the keys trusted for manifest signatures
+fn trust_args() -> [Arg; 2] {
+    [
+        arg!(--"trusted-key" <KEY> "Additional base64 encoded Ed25519 public key trusted for manifest signatures, can be repeated")
+            .action(ArgAction::Append),
+        arg!(--"allow-unsigned" "Accept manifests that are unsigned or whose signature cannot be verified. Signatures are enforced by default")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Argument of the commands that rewrite the manifest, which drops its original signature
+fn signing_key_arg() -> Arg {
+    arg!(--"signing-key" <FILE> "File holding a base64 encoded Ed25519 secret key to sign the rewritten manifest with. Without it the manifest is unsigned")
+}
+
+/// Values of `--trusted-key`, checked to be valid keys
+fn parse_trusted_keys(matches: &ArgMatches) -> Result<Vec<String>, &'static str> {
+    let keys: Vec<String> = matches
+        .get_many::<String>("trusted-key")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    for key in &keys {
+        super::signature::parse_key(key)?;
+    }
+    Ok(keys)
+}
+
+/// Argument selecting optional content groups
+fn group_arg() -> Arg {
+    arg!(--group <NAME> "Install an optional content group, remembered for future runs, can be repeated")
+        .action(ArgAction::Append)
+}
+
+/// Values of a repeatable group argument
+fn parse_groups(matches: &ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .unwrap_or_default()
+        .cloned()
+        .collect()
 }
 
 impl Config {
     pub fn build() -> Result<Config, &'static str> {
         let matches = Command::new("rs_manifest_patcher")
-            .arg(arg!(-m --manifest <String> "Path to manifest.json file or URL (e.g., http://localhost:8080/manifest.json)")
-                .default_value("https://updater.project-epoch.net/api/v2/manifest?environment=production"))
-            .arg(arg!(-p --provider <String> "Provider to use for downloads")
-                .value_parser(Provider::known_keys())
-                .default_value("cloudflare")
-                .help("Available providers: cloudflare (Server #1), digitalocean (Server #2), none (Server #3 - Slowest)"))
+            .args(manifest_args())
+            .arg(provider_arg())
+            .arg(arg!(-b --"base-path" <PATH> "Game directory to patch. Defaults to an auto-detected game directory, or the current directory"))
+            .arg(arg!(--profiles <FILE> "Profile registry file. Defaults to profiles.json in the user configuration directory"))
+            .arg(arg!(--"remember-provider" "Remember the selected provider for this installation").action(ArgAction::SetTrue))
+            .arg(arg!(--proxy <URL> "Proxy for all requests (http, https or socks5). Defaults to the HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables"))
+            .arg(arg!(--"connect-timeout" <SECS> "Connection timeout in seconds")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("10"))
+            .arg(arg!(--"read-timeout" <SECS> "Timeout in seconds while waiting for data")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("30"))
+            .arg(arg!(--"ca-cert" <FILE> "Additional trusted CA certificate (PEM), can be repeated")
+                .action(ArgAction::Append))
+            .arg(arg!(-H --header <HEADER> "Extra request header as 'Name: value', can be repeated")
+                .value_parser(http::parse_header)
+                .action(ArgAction::Append))
+            .arg(arg!(--token <TOKEN> "Bearer token for private manifests. Defaults to the PATCHER_TOKEN environment variable"))
+            .args(trust_args())
+            .arg(arg!(--"manifest-signature" <FILE> "Detached signature of a manifest read from stdin with --manifest -"))
+            .arg(arg!(--"reset-manifest-baseline" "Forget the newest manifest seen from the manifest sources, so an older or republished manifest is accepted").action(ArgAction::SetTrue))
+            .arg(arg!(--verify "Hash every file even if the manifest was already applied").action(ArgAction::SetTrue))
+            .arg(arg!(--"hard-link" "Hard link local files with matching contents instead of copying them").action(ArgAction::SetTrue))
+            .arg(arg!(--"modified-policy" <POLICY> "What to do with files changed since they were installed")
+                .value_parser(ModifiedPolicy::known_keys())
+                .default_value("overwrite"))
+            .arg(arg!(--protect <RULE> "Policy for modified files matching a pattern as 'PATTERN=POLICY', can be repeated")
+                .value_parser(parse_policy_rule)
+                .action(ArgAction::Append))
+            .arg(arg!(--exclude <PATTERN> "Do not touch files matching a gitignore-style pattern, can be repeated")
+                .action(ArgAction::Append))
+            .arg(arg!(--include <PATTERN> "Patch files matching a pattern even if they are ignored, can be repeated")
+                .action(ArgAction::Append))
+            .arg(arg!(--"skip-custom" "Only patch stock client files, leave custom server content untouched")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("only-custom"))
+            .arg(arg!(--"only-custom" "Only patch custom server content, leave stock client files untouched")
+                .action(ArgAction::SetTrue))
+            .arg(group_arg())
+            .arg(locale_arg())
+            .arg(arg!(--"without-group" <NAME> "Deselect an optional content group and remove its files, can be repeated")
+                .action(ArgAction::Append))
+            .arg(arg!(--"cache-dir" <DIR> "Download cache shared between installations. Defaults to the PATCHER_CACHE_DIR environment variable"))
+            .arg(arg!(--"cache-size" <MIB> "Size limit of the download cache in MiB, least recently used files are pruned first")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("10240"))
+            .arg(arg!(--segments <N> "Connections used to download a large file when the server supports range requests, from 1 to 16. 1 disables segmented downloads")
+                .value_parser(clap::value_parser!(u64).range(1..=MAX_SEGMENTS as u64))
+                .default_value("4"))
+            .arg(arg!(--"segment-threshold" <MIB> "Size in MiB from which files are downloaded over several connections")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("64"))
+            .arg(arg!(--bundle <FILE> "Patch from an offline bundle created by the export command instead of downloading"))
+            .arg(arg!(-y --yes "Do not ask for confirmation before downloading. Modified files with the ask policy are kept and listed").action(ArgAction::SetTrue))
+            .subcommand(
+                Command::new("uninstall")
+                    .about("Remove custom server content and restore the stock client files"),
+            )
+            .subcommand(
+                Command::new("export")
+                    .about("Write the manifest and the files needed to patch the game directory into an offline bundle")
+                    .arg(arg!(<FILE> "Bundle file to write"))
+                    .arg(arg!(--all "Include every file of the manifest, not only the ones this installation needs").action(ArgAction::SetTrue)),
+            )
+            .subcommand(
+                Command::new("mirror")
+                    .about("Download every file of the manifest into a directory for self-hosting")
+                    .arg(arg!(<DIR> "Mirror directory, files are laid out by their manifest path"))
+                    .arg(arg!(--"base-url" <URL> "URL the mirror directory is served from, used in the rewritten manifest")
+                        .required(true)
+                        .value_parser(|url: &str| Url::parse(url).map_err(|e| e.to_string())))
+                    .arg(signing_key_arg()),
+            )
+            .subcommand(
+                Command::new("serve")
+                    .about("Serve the verified files of the game directory to other patchers over HTTP")
+                    .arg(arg!(--bind <ADDR> "Address to listen on, e.g. 0.0.0.0:8080 to serve every network the machine is on")
+                        .required(true)
+                        .value_parser(clap::value_parser!(SocketAddr)))
+                    .arg(arg!(--"base-url" <URL> "URL clients reach the server at. Defaults to the host each client connected to")
+                        .value_parser(|url: &str| Url::parse(url).map_err(|e| e.to_string())))
+                    .arg(signing_key_arg()),
+            )
+            .subcommand(
+                Command::new("import")
+                    .about("Patch the game directory from an offline bundle, same as --bundle")
+                    .arg(arg!(<FILE> "Bundle file created by the export command")),
+            )
+            .subcommand(
+                Command::new("status").about("Show the manifest last applied to the game directory"),
+            )
+            .subcommand(
+                Command::new("providers").about("List the download providers offered by the manifest"),
+            )
+            .subcommand(
+                Command::new("cache")
+                    .about("Manage the download cache")
+                    .subcommand_required(true)
+                    .subcommand(Command::new("list").about("List cached files, least recently used first"))
+                    .subcommand(Command::new("verify").about("Remove cached files that do not match their hash"))
+                    .subcommand(Command::new("purge").about("Remove every cached file")),
+            )
+            .subcommand(
+                Command::new("profile")
+                    .about("Manage named game installations")
+                    .subcommand_required(true)
+                    .subcommand(Command::new("list").about("List registered profiles"))
+                    .subcommand(
+                        Command::new("add")
+                            .about("Register a game installation")
+                            .arg(arg!(<NAME> "Profile name"))
+                            .arg(arg!(-b --"base-path" <PATH> "Game directory of the profile").required(true))
+                            .args(manifest_args())
+                            .arg(provider_arg())
+                            .arg(group_arg())
+                            .arg(locale_arg())
+                            .args(trust_args())
+                            .arg(arg!(--replace "Replace an existing profile with the same name").action(ArgAction::SetTrue)),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a profile")
+                            .arg(arg!(<NAME> "Profile name")),
+                    )
+                    .subcommand(
+                        Command::new("update")
+                            .about("Patch one or more profiles")
+                            .arg(arg!([NAME] ... "Profiles to patch"))
+                            .arg(arg!(--all "Patch every registered profile").action(ArgAction::SetTrue))
+                            .group(ArgGroup::new("targets").args(["NAME", "all"]).required(true)),
+                    ),
+            )
             .get_matches();
 
-        let manifest_str = matches.get_one::<String>("manifest").unwrap().to_string();
-        let manifest = Location::parse(manifest_str)?;
+        let (manifest_locations, channel) = parse_manifest(&matches)?;
+
+        let http = HttpConfig {
+            proxy: matches.get_one::<String>("proxy").cloned(),
+            connect_timeout: matches
+                .get_one::<u64>("connect-timeout")
+                .map(|secs| std::time::Duration::from_secs(*secs)),
+            read_timeout: matches
+                .get_one::<u64>("read-timeout")
+                .map(|secs| std::time::Duration::from_secs(*secs)),
+            ca_certificates: matches
+                .get_many::<String>("ca-cert")
+                .unwrap_or_default()
+                .map(PathBuf::from)
+                .collect(),
+            headers: matches
+                .get_many::<(String, String)>("header")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+            bearer_token: matches
+                .get_one::<String>("token")
+                .cloned()
+                .or_else(|| std::env::var("PATCHER_TOKEN").ok()),
+        };
+
+        let mut trust = TrustedKeys::default();
+        if let Some(path) = TrustedKeys::default_path() {
+            trust.add_keys_file(&path)?;
+        }
+        for key in parse_trusted_keys(&matches)? {
+            trust.add_key(&key)?;
+        }
+        trust.allow_unsigned = matches.get_flag("allow-unsigned");
+
+        let mut modified_policy = PolicyRules::default();
+        modified_policy.default = matches
+            .get_one::<String>("modified-policy")
+            .and_then(|policy| policy.parse().ok())
+            .unwrap_or_default();
+        for rule in matches.get_many::<String>("protect").unwrap_or_default() {
+            modified_policy
+                .add(rule)
+                .map_err(|_| "Invalid --protect rule")?;
+        }
+
+        let ignore = parse_ignore(&matches)?;
+
+        let profiles_path = match matches.get_one::<String>("profiles") {
+            Some(path) => PathBuf::from(path),
+            None => ProfileRegistry::default_path()
+                .ok_or("Failed to determine the configuration directory")?,
+        };
+
+        let action = match matches.subcommand() {
+            Some(("providers", _)) => Action::Providers,
+            Some(("status", _)) => Action::Status,
+            Some(("uninstall", _)) => Action::Uninstall,
+            Some(("export", export_matches)) => Action::Export {
+                path: PathBuf::from(export_matches.get_one::<String>("FILE").unwrap()),
+                all: export_matches.get_flag("all"),
+            },
+            Some(("mirror", mirror_matches)) => Action::Mirror {
+                dir: PathBuf::from(mirror_matches.get_one::<String>("DIR").unwrap()),
+                base_url: mirror_matches.get_one::<Url>("base-url").unwrap().clone(),
+                signing_key: mirror_matches
+                    .get_one::<String>("signing-key")
+                    .map(PathBuf::from),
+            },
+            Some(("serve", serve_matches)) => Action::Serve {
+                bind: *serve_matches.get_one::<SocketAddr>("bind").unwrap(),
+                base_url: serve_matches.get_one::<Url>("base-url").cloned(),
+                signing_key: serve_matches
+                    .get_one::<String>("signing-key")
+                    .map(PathBuf::from),
+            },
+            Some(("import", _)) => Action::Patch,
+            Some(("cache", cache_matches)) => match cache_matches.subcommand() {
+                Some(("list", _)) => Action::CacheList,
+                Some(("verify", _)) => Action::CacheVerify,
+                Some(("purge", _)) => Action::CachePurge,
+                _ => unreachable!("subcommand_required prevents this"),
+            },
+            Some(("profile", profile_matches)) => match profile_matches.subcommand() {
+                Some(("list", _)) => Action::ProfileList,
+                Some(("add", add_matches)) => {
+                    let base_path =
+                        PathBuf::from(add_matches.get_one::<String>("base-path").unwrap());
+                    game::check_base_path(&base_path)?;
+                    let base_path = std::path::absolute(&base_path)
+                        .map_err(|_| "Failed to resolve base path")?;
+
+                    let (locations, channel) = parse_manifest(add_matches)?;
+                    // Store file manifests as absolute paths so the profile works from any directory
+                    let mut manifests = locations
+                        .into_iter()
+                        .map(|location| match location {
+                            Location::FilePath(path) => std::path::absolute(path)
+                                .map(|path| path.display().to_string())
+                                .map_err(|_| "Failed to resolve manifest path"),
+                            Location::Stdin => Err("A profile cannot read its manifest from stdin"),
+                            location => Ok(location.to_string()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let manifest = manifests.remove(0);
+
+                    Action::ProfileAdd {
+                        profile: Profile {
+                            name: add_matches.get_one::<String>("NAME").unwrap().to_string(),
+                            base_path,
+                            manifest,
+                            fallback_manifests: manifests,
+                            provider: match parse_provider(add_matches) {
+                                Some(ProviderChoice::Fixed(provider)) => provider,
+                                Some(ProviderChoice::Fastest) => {
+                                    return Err("Profiles need a fixed provider, auto is only available on the command line")
+                                }
+                                None => Provider::Cloudflare,
+                            },
+                            channel,
+                            groups: parse_groups(add_matches, "group"),
+                            locale: add_matches.get_one::<String>("locale").cloned(),
+                            trusted_keys: parse_trusted_keys(add_matches)?,
+                            allow_unsigned: add_matches.get_flag("allow-unsigned"),
+                        },
+                        replace: add_matches.get_flag("replace"),
+                    }
+                }
+                Some(("remove", remove_matches)) => Action::ProfileRemove(
+                    remove_matches
+                        .get_one::<String>("NAME")
+                        .unwrap()
+                        .to_string(),
+                ),
+                Some(("update", update_matches)) => Action::ProfileUpdate(
+                    update_matches
+                        .get_many::<String>("NAME")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                ),
+                _ => unreachable!("subcommand_required prevents this"),
+            },
+            _ => Action::Patch,
+        };
+
+        let base_path = match matches.get_one::<String>("base-path") {
+            Some(path) => PathBuf::from(path),
+            None => match game::detect_game_dir() {
+                Some(dir) => dir,
+                None => std::env::current_dir().map_err(|_| "Failed to get current directory")?,
+            },
+        };
+        if matches!(
+            action,
+            Action::Patch | Action::Uninstall | Action::Status | Action::Serve { .. }
+        ) {
+            game::check_base_path(&base_path)?;
+        }
 
-        let provider_str = matches.get_one::<String>("provider").unwrap().as_str();
-        let provider = Provider::from_str(provider_str).unwrap();
+        let provider = parse_provider(&matches)
+            .or_else(|| super::probe::load_remembered(&base_path).map(ProviderChoice::Fixed))
+            .unwrap_or(ProviderChoice::Fixed(Provider::Cloudflare));
+
+        let custom = if matches!(action, Action::Uninstall) {
+            CustomMode::Remove
+        } else if matches.get_flag("skip-custom") {
+            CustomMode::Skip
+        } else if matches.get_flag("only-custom") {
+            CustomMode::Only
+        } else {
+            CustomMode::Install
+        };
+
+        let cache = matches
+            .get_one::<String>("cache-dir")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("PATCHER_CACHE_DIR").map(PathBuf::from))
+            .map(|dir| {
+                let max_size = *matches.get_one::<u64>("cache-size").unwrap();
+                DownloadCache::new(dir, max_size * 1024 * 1024)
+            });
 
         Ok(Config {
-            manifest_location: manifest,
+            manifest_locations,
             manifest_provider: provider,
+            channel,
+            base_path,
+            profiles_path,
+            http,
+            trust,
+            stdin_signature: matches
+                .get_one::<String>("manifest-signature")
+                .map(PathBuf::from),
+            reset_manifest_baseline: matches.get_flag("reset-manifest-baseline"),
+            assume_yes: matches.get_flag("yes"),
+            full_verify: matches.get_flag("verify"),
+            remember_provider: matches.get_flag("remember-provider"),
+            modified_policy,
+            ignore,
+            custom,
+            groups: parse_groups(&matches, "group"),
+            without_groups: parse_groups(&matches, "without-group"),
+            locale: matches.get_one::<String>("locale").cloned(),
+            hard_link: matches.get_flag("hard-link"),
+            cache,
+            bundle: matches
+                .subcommand_matches("import")
+                .and_then(|import_matches| import_matches.get_one::<String>("FILE"))
+                .or_else(|| matches.get_one::<String>("bundle"))
+                .map(PathBuf::from),
+            segments: *matches.get_one::<u64>("segments").unwrap() as usize,
+            segment_threshold: *matches.get_one::<u64>("segment-threshold").unwrap() * 1024 * 1024,
+            action,
+        })
+    }
+}
+
+fn locale_arg() -> Arg {
+    arg!(--locale <LOCALE> "Client locale such as enUS or deDE. Defaults to the locale detected in the game directory")
+        .value_parser(|locale: &str| {
+            game::parse_locale(locale)
+                .map(String::from)
+                .ok_or_else(|| format!("Unknown locale, expected one of: {}", game::LOCALES.join(", ")))
         })
+}
+
+/// Build ignore rules from `--exclude`/`--include`, keeping their command line order
+fn parse_ignore(matches: &ArgMatches) -> Result<IgnoreRules, &'static str> {
+    let mut patterns: Vec<(usize, &String, bool)> = Vec::new();
+    for (id, include) in [("exclude", false), ("include", true)] {
+        if let (Some(indices), Some(values)) =
+            (matches.indices_of(id), matches.get_many::<String>(id))
+        {
+            patterns.extend(indices.zip(values).map(|(i, value)| (i, value, include)));
+        }
+    }
+    patterns.sort_by_key(|(index, _, _)| *index);
+
+    let mut ignore = IgnoreRules::default();
+    for (_, pattern, include) in patterns {
+        let added = if include {
+            ignore.include(pattern)
+        } else {
+            ignore.exclude(pattern)
+        };
+        added.map_err(|_| "Invalid --include/--exclude pattern")?;
     }
+    Ok(ignore)
+}
+
+/// Validate a `--protect` rule without keeping the parsed result
+fn parse_policy_rule(rule: &str) -> Result<String, String> {
+    PolicyRules::default().add(rule)?;
+    Ok(rule.to_string())
 }
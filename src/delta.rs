@@ -0,0 +1,49 @@
+//! Binary deltas between two versions of a file.
+//!
+//! A delta is a zstd frame compressed with the old file as reference prefix, as produced by
+//! `zstd --patch-from=<old> <new>`.
+
+use std::io::{self, Write};
+
+/// Largest back-reference distance accepted when applying a delta, allows files up to 2 GiB
+const WINDOW_LOG_MAX: u32 = 31;
+
+/// Apply a delta to the contents of the source file and return the target contents
+///
+/// # Examples
+///
+/// ```
+/// use rs_manifest_patcher::delta;
+/// let source = b"patch data version 1".repeat(100);
+/// let target = b"patch data version 2".repeat(100);
+/// let patch = delta::create(&source, &target).unwrap();
+/// assert_eq!(delta::apply(&source, &patch).unwrap(), target);
+/// ```
+pub fn apply(source: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    let mut target = Vec::new();
+    apply_to(source, patch, &mut target)?;
+    Ok(target)
+}
+
+/// Apply a delta to the contents of the source file, streaming the target contents into
+/// `target` and returning their size. Only the source has to be held in memory, zstd uses it
+/// as reference prefix.
+pub fn apply_to(source: &[u8], patch: &[u8], target: &mut impl Write) -> io::Result<u64> {
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(patch, source)?;
+    decoder.window_log_max(WINDOW_LOG_MAX)?;
+    io::copy(&mut decoder, target)
+}
+
+/// Create a delta turning the source contents into the target contents
+pub fn create(source: &[u8], target: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, source)?;
+    encoder.long_distance_matching(true)?;
+    encoder.window_log(WINDOW_LOG_MAX.min(window_log(source.len().max(target.len()))))?;
+    encoder.write_all(target)?;
+    encoder.finish()
+}
+
+/// Smallest window log covering `len` bytes
+fn window_log(len: usize) -> u32 {
+    (usize::BITS - len.leading_zeros()).max(10)
+}
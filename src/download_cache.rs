@@ -0,0 +1,164 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::manifest::{hash_file, is_md5_hash};
+
+#[derive(Debug, Clone)]
+/// Downloaded files shared between installations, stored by hash.
+///
+/// Whole files are downloaded into a partial entry, which becomes an entry once its hash was
+/// verified and is then copied into the installation. Files rebuilt from deltas or changed
+/// chunks are added once they are installed.
+///
+/// Files are kept at `<dir>/<first two hash characters>/<hash>`. The modification time of an
+/// entry records when it was last used, so the least recently used entries are pruned first.
+pub struct DownloadCache {
+    pub dir: PathBuf,
+    /// Size limit in bytes enforced by [`DownloadCache::prune`]
+    pub max_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub hash: String,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+impl DownloadCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        DownloadCache { dir, max_size }
+    }
+
+    /// Location of the entry for a hash. Manifests only hold MD5 hashes, which cannot name a
+    /// path outside the cache.
+    pub fn path(&self, hash: &str) -> PathBuf {
+        debug_assert!(is_md5_hash(hash), "not an MD5 hash: {hash}");
+        let hash = hash.to_lowercase();
+        self.dir.join(hash.get(..2).unwrap_or("00")).join(hash)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.path(hash).is_file()
+    }
+
+    /// Partial file a download of `hash` is written to before it becomes an entry. Every process
+    /// has its own, so concurrent downloads of the same file do not overwrite each other.
+    pub fn partial_path(&self, hash: &str) -> io::Result<PathBuf> {
+        let path = self.path(hash);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(path.with_extension(format!("{}.partial", std::process::id())))
+    }
+
+    /// Turn a verified partial file from [`DownloadCache::partial_path`] into the entry of `hash`
+    pub fn commit(&self, hash: &str, partial: &Path) -> io::Result<()> {
+        std::fs::rename(partial, self.path(hash))
+    }
+
+    /// Copy an entry to `dest`, as a reflink where the filesystem supports it, and mark the
+    /// entry as used
+    pub fn materialize(&self, hash: &str, dest: &Path) -> io::Result<()> {
+        let path = self.path(hash);
+        match std::fs::remove_file(dest) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        reflink_copy::reflink_or_copy(&path, dest)?;
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now())
+    }
+
+    /// Store a copy of an installed file. Existing entries are only marked as used.
+    pub fn insert(&self, hash: &str, source: &Path) -> io::Result<()> {
+        let path = self.path(hash);
+        if path.is_file() {
+            return std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(SystemTime::now());
+        }
+        // Entries appear atomically so other installations never see a partial file
+        let partial = self.partial_path(hash)?;
+        reflink_copy::reflink_or_copy(source, &partial)?;
+        self.commit(hash, &partial)
+    }
+
+    pub fn remove(&self, hash: &str) -> io::Result<()> {
+        std::fs::remove_file(self.path(hash))
+    }
+
+    /// All entries, least recently used first. A missing cache directory has no entries.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        let dirs = match std::fs::read_dir(&self.dir) {
+            Ok(dirs) => dirs,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e),
+        };
+        for dir in dirs {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(dir.path())? {
+                let file = file?;
+                let hash = file.file_name().to_string_lossy().to_string();
+                let metadata = file.metadata()?;
+                // Partial entries and files that are not named by a hash are not entries
+                if !metadata.is_file() || !is_md5_hash(&hash) {
+                    continue;
+                }
+                entries.push(CacheEntry {
+                    hash,
+                    size: metadata.len(),
+                    last_used: metadata.modified()?,
+                });
+            }
+        }
+        entries.sort_by_key(|entry| entry.last_used);
+        Ok(entries)
+    }
+
+    /// Remove entries whose contents no longer match their hash and return them
+    pub fn verify(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut corrupt = Vec::new();
+        for entry in self.entries()? {
+            if hash_file(&self.path(&entry.hash))? != entry.hash {
+                self.remove(&entry.hash)?;
+                corrupt.push(entry);
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Remove least recently used entries until the cache fits its size limit and return them
+    pub fn prune(&self) -> io::Result<Vec<CacheEntry>> {
+        let entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut removed = Vec::new();
+        for entry in entries {
+            if size <= self.max_size {
+                break;
+            }
+            self.remove(&entry.hash)?;
+            size -= entry.size;
+            removed.push(entry);
+        }
+        Ok(removed)
+    }
+
+    /// Remove every entry and return them
+    pub fn purge(&self) -> io::Result<Vec<CacheEntry>> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            self.remove(&entry.hash)?;
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Compression applied to a file for transfer
+pub enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+enum Decoder {
+    Zstd(zstd::stream::zio::Writer<Vec<u8>, zstd::stream::raw::Decoder<'static>>),
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+}
+
+/// Decompresses a transfer encoded file chunk by chunk as it is received.
+///
+/// # Examples
+///
+/// ```
+/// use rs_manifest_patcher::encoding::{Decompressor, Encoding};
+/// let compressed = zstd::encode_all(&b"patch data"[..], 3).unwrap();
+/// let mut decompressor = Decompressor::new(Encoding::Zstd).unwrap();
+/// let (head, tail) = compressed.split_at(compressed.len() / 2);
+/// let mut contents = decompressor.decompress(head).unwrap();
+/// contents.extend(decompressor.decompress(tail).unwrap());
+/// contents.extend(decompressor.finish().unwrap());
+/// assert_eq!(contents, b"patch data");
+/// ```
+pub struct Decompressor {
+    decoder: Decoder,
+}
+
+impl Decompressor {
+    pub fn new(encoding: Encoding) -> io::Result<Self> {
+        let decoder = match encoding {
+            Encoding::Zstd => Decoder::Zstd(zstd::stream::zio::Writer::new(
+                Vec::new(),
+                zstd::stream::raw::Decoder::new()?,
+            )),
+            Encoding::Gzip => Decoder::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+        };
+        Ok(Decompressor { decoder })
+    }
+
+    /// Feed a compressed chunk and return the bytes decompressed so far
+    pub fn decompress(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match &mut self.decoder {
+            Decoder::Zstd(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.flush()?;
+                Ok(std::mem::take(decoder.writer_mut()))
+            }
+            Decoder::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.flush()?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+
+    /// Finish the stream and return the remaining bytes, fails if the stream is truncated
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        match self.decoder {
+            Decoder::Zstd(mut decoder) => {
+                decoder.finish()?;
+                Ok(decoder.into_inner().0)
+            }
+            Decoder::Gzip(decoder) => decoder.finish(),
+        }
+    }
+}
@@ -38,3 +38,38 @@ pub fn eta_to_human_readable(seconds: f64) -> String {
         format!("{}s", secs as u32)
     }
 }
+
+/// Converts an age in seconds to a short human-readable string.
+///
+/// # Examples
+///
+/// ```
+/// use rs_manifest_patcher::format::age_to_human_readable;
+/// assert_eq!(age_to_human_readable(30), "just now");
+/// assert_eq!(age_to_human_readable(125), "2m ago");
+/// assert_eq!(age_to_human_readable(7260), "2h01m ago");
+/// assert_eq!(age_to_human_readable(180000), "2d02h ago");
+/// ```
+pub fn age_to_human_readable(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let hours = (seconds % 86400) / 3600;
+    let minutes = (seconds % 3600) / 60;
+
+    if days > 0 {
+        format!("{days}d{hours:02}h ago")
+    } else if hours > 0 {
+        format!("{hours}h{minutes:02}m ago")
+    } else if minutes > 0 {
+        format!("{minutes}m ago")
+    } else {
+        String::from("just now")
+    }
+}
+
+/// Returns the current time as seconds since the Unix epoch
+pub fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
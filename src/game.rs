@@ -1,7 +1,9 @@
 /// Verifies the integrity of a game installation by checking for required files and directories.
 ///
-/// This function checks if all required game files and directories exist in the specified game directory.
-/// It looks for specific files like DLLs and MPQ files, as well as essential directories.
+/// The base client files are not part of the patch manifest, so this checks for them separately:
+/// `Battle.net.dll`, `Data/lichking.MPQ`, `Data/patch-3.MPQ` and the locale archive
+/// `Data/<locale>/locale-<locale>.MPQ`. The locale is detected with [`detect_locale`]. Missing
+/// entries are printed.
 ///
 /// # Arguments
 /// * `game_dir` - A Path reference pointing to the root directory of the game installation
@@ -20,11 +22,22 @@
 ///     Err(e) => println!("Error checking game files: {}", e),
 /// }
 /// ```
-#[allow(dead_code)]
 pub fn verify_game_integrity(game_dir: &std::path::Path) -> Result<bool, std::io::Error> {
-    let required_files = ["Battle.net.dll", "Data/lichking.MPQ", "Data/patch-3.MPQ"];
+    let Some(locale) = detect_locale(game_dir) else {
+        println!("Missing locale directory in Data");
+        return Ok(false);
+    };
+
+    let locale_dir = format!("Data/{locale}");
+    let locale_file = format!("{locale_dir}/locale-{locale}.MPQ");
+    let required_files = [
+        "Battle.net.dll",
+        "Data/lichking.MPQ",
+        "Data/patch-3.MPQ",
+        locale_file.as_str(),
+    ];
 
-    let required_dirs = ["Data"];
+    let required_dirs = ["Data", locale_dir.as_str()];
 
     // Check required directories
     for dir in required_dirs.iter() {
@@ -47,6 +60,107 @@ pub fn verify_game_integrity(game_dir: &std::path::Path) -> Result<bool, std::io
     Ok(true)
 }
 
+/// Locales of the WotLK client, each with its own `Data/<locale>` directory.
+pub const LOCALES: [&str; 11] = [
+    "enUS", "enGB", "deDE", "frFR", "esES", "esMX", "ruRU", "koKR", "zhCN", "zhTW", "enTW",
+];
+
+/// Returns the canonical spelling of a known locale, ignoring case.
+pub fn parse_locale(locale: &str) -> Option<&'static str> {
+    LOCALES
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(locale))
+        .copied()
+}
+
+/// Detects the client locale of a game installation.
+///
+/// The `SET locale` line of `WTF/Config.wtf` is used if it names a locale, otherwise the
+/// first `Data/<locale>` directory containing its `locale-<locale>.MPQ`, then the first
+/// `Data/<locale>` directory at all.
+pub fn detect_locale(game_dir: &std::path::Path) -> Option<&'static str> {
+    let configured = std::fs::read_to_string(game_dir.join("WTF/Config.wtf"))
+        .ok()
+        .and_then(|config| {
+            config.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("SET locale ")?;
+                parse_locale(value.trim().trim_matches('"'))
+            })
+        });
+    if configured.is_some() {
+        return configured;
+    }
+
+    let data_dir = game_dir.join("Data");
+    let installed: Vec<&str> = LOCALES
+        .iter()
+        .filter(|locale| data_dir.join(locale).is_dir())
+        .copied()
+        .collect();
+    installed
+        .iter()
+        .find(|locale| {
+            data_dir
+                .join(locale)
+                .join(format!("locale-{locale}.MPQ"))
+                .is_file()
+        })
+        .or(installed.first())
+        .copied()
+}
+
+/// Files whose presence identifies a directory as a game client installation.
+const CLIENT_MARKERS: [&str; 3] = ["Wow.exe", "Battle.net.dll", "Data/lichking.MPQ"];
+
+/// Returns true if `dir` contains any of the known client files.
+pub fn is_game_dir(dir: &std::path::Path) -> bool {
+    CLIENT_MARKERS
+        .iter()
+        .any(|marker| dir.join(marker).is_file())
+}
+
+/// Attempts to locate the game directory by looking for known client files.
+///
+/// The current working directory is checked first, followed by the directory containing
+/// the patcher executable and its parent. Returns `None` if no candidate looks like a
+/// game installation.
+pub fn detect_game_dir() -> Option<std::path::PathBuf> {
+    let mut candidates = Vec::new();
+    if let Ok(current_dir) = std::env::current_dir() {
+        candidates.push(current_dir);
+    }
+    if let Ok(exe) = std::env::current_exe() {
+        // The executable's own directory and its parent
+        candidates.extend(exe.ancestors().skip(1).take(2).map(|dir| dir.to_path_buf()));
+    }
+    candidates.into_iter().find(|dir| is_game_dir(dir))
+}
+
+/// Checks that `base_path` exists, is a directory and can be written to.
+///
+/// Writability is tested by creating and removing a small probe file, which is more
+/// reliable than inspecting permission bits across platforms. A probe left behind by an
+/// interrupted run is reused rather than mistaken for an unwritable directory.
+pub fn check_base_path(base_path: &std::path::Path) -> Result<(), &'static str> {
+    if !base_path.exists() {
+        return Err("Base path does not exist");
+    }
+    if !base_path.is_dir() {
+        return Err("Base path is not a directory");
+    }
+
+    let probe = base_path.join(".rs_manifest_patcher_write_check");
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .map_err(|_| "Base path is not writable")?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
 #[allow(dead_code)]
 pub fn launch(client_directory: &std::path::Path, executable_name: String) -> std::io::Result<()> {
     // Clear the cache directory
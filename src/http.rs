@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+
+/// User-Agent sent with every request
+pub const USER_AGENT: &str = concat!(
+    "rs_manifest_patcher/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/sogladev/rs_manifest_patcher)"
+);
+
+#[derive(Debug, Clone, Default)]
+/// Settings for the HTTP client shared by manifest fetching, probing and downloads.
+///
+/// # Fields
+///
+/// - `proxy` - Proxy URL (`http://`, `https://`, `socks5://` or `socks5h://`) used for all requests.
+///   When unset, the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` variables apply.
+/// - `connect_timeout` - Maximum time to establish a connection.
+/// - `read_timeout` - Maximum time to wait for data on an established connection.
+/// - `ca_certificates` - PEM files with additional trusted root certificates.
+/// - `headers` - Extra headers sent with every request.
+/// - `bearer_token` - Token sent as `Authorization: Bearer <token>` with every request.
+pub struct HttpConfig {
+    pub proxy: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub ca_certificates: Vec<PathBuf>,
+    pub headers: Vec<(String, String)>,
+    pub bearer_token: Option<String>,
+}
+
+/// Parse a `Name: value` header argument
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("Invalid header '{header}', expected 'Name: value'")),
+    }
+}
+
+/// Build the HTTP client used for all network access
+pub fn build_client(config: &HttpConfig) -> Result<reqwest::Client, Box<dyn Error>> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    if let Some(token) = &config.bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers);
+
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    if let Some(timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = config.read_timeout {
+        builder = builder.read_timeout(timeout);
+    }
+    for path in &config.ca_certificates {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("Failed to read CA certificate {}: {e}", path.display()))?;
+        for certificate in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder.build()?)
+}
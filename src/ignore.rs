@@ -0,0 +1,89 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::pattern::Pattern;
+
+/// Name of the ignore file in the game directory
+pub const IGNORE_FILE: &str = ".patcherignore";
+
+#[derive(Debug, Clone, Default)]
+/// Gitignore-style rules for manifest files the patcher must not touch.
+///
+/// Each rule either ignores or re-includes matching paths. The last matching rule wins.
+pub struct IgnoreRules {
+    rules: Vec<(Pattern, bool)>,
+}
+
+impl IgnoreRules {
+    /// Path of the ignore file for a game directory
+    pub fn path(base_path: &Path) -> PathBuf {
+        base_path.join(IGNORE_FILE)
+    }
+
+    /// Load the ignore file of a game directory, an absent file has no rules
+    pub fn load(base_path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(Self::path(base_path)) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parse ignore file contents.
+    ///
+    /// Blank lines and lines starting with `#` are skipped, a leading `!` re-includes paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_manifest_patcher::ignore::IgnoreRules;
+    /// let rules = IgnoreRules::parse("# pinned\nWow.exe\nData/enUS/\n!Data/enUS/realmlist.wtf\n");
+    /// assert!(rules.is_ignored("Wow.exe"));
+    /// assert!(rules.is_ignored("Data/enUS/patch-enUS-4.MPQ"));
+    /// assert!(!rules.is_ignored("Data/enUS/realmlist.wtf"));
+    /// assert!(!rules.is_ignored("Data/patch-4.MPQ"));
+    /// ```
+    pub fn parse(contents: &str) -> Self {
+        let mut rules = Self::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, ignore) = match line.strip_prefix('!') {
+                Some(pattern) => (pattern, false),
+                None => (line.strip_prefix('\\').unwrap_or(line), true),
+            };
+            if let Ok(pattern) = Pattern::new(pattern) {
+                rules.rules.push((pattern, ignore));
+            }
+        }
+        rules
+    }
+
+    /// Ignore paths matching the pattern
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), String> {
+        self.rules.push((Pattern::new(pattern)?, true));
+        Ok(())
+    }
+
+    /// Re-include paths matching the pattern
+    pub fn include(&mut self, pattern: &str) -> Result<(), String> {
+        self.rules.push((Pattern::new(pattern)?, false));
+        Ok(())
+    }
+
+    /// Append rules that take precedence over the current ones
+    pub fn extend(&mut self, other: IgnoreRules) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Check whether a manifest path is ignored
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.matches(path))
+            .is_some_and(|(_, ignore)| *ignore)
+    }
+}
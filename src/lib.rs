@@ -1,11 +1,29 @@
+pub mod atomic_file;
 pub mod banner;
+pub mod bundle;
+pub mod channel;
 pub mod config;
+pub mod delta;
+pub mod download_cache;
+pub mod encoding;
 pub mod format;
 pub mod game;
+pub mod http;
+pub mod ignore;
 pub mod manifest;
+pub mod manifest_cache;
+pub mod mirror;
+pub mod pattern;
+pub mod policy;
+pub mod probe;
+pub mod profile;
 pub mod progress;
 pub mod prompt;
+pub mod serve;
+pub mod signature;
+pub mod state;
 pub mod transaction;
+pub mod transport;
 
 pub use config::Config;
 pub use manifest::Location;
@@ -1,8 +1,24 @@
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::path::PathBuf;
 use std::process;
 
-use rs_manifest_patcher::{banner, prompt, Progress};
-use rs_manifest_patcher::{Config, Manifest, Transaction};
+use colored::Colorize;
+use humansize::BINARY;
+use rs_manifest_patcher::bundle::Bundle;
+use rs_manifest_patcher::channel::Channel;
+use rs_manifest_patcher::config::Action;
+use rs_manifest_patcher::ignore::IgnoreRules;
+use rs_manifest_patcher::manifest::{BuildOptions, Provider};
+use rs_manifest_patcher::manifest_cache::ManifestCache;
+use rs_manifest_patcher::probe::ProviderChoice;
+use rs_manifest_patcher::profile::ProfileRegistry;
+use rs_manifest_patcher::serve::FileServer;
+use rs_manifest_patcher::signature::{self, TrustedKeys};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::{banner, format, game, http, mirror, probe, prompt, Progress};
+use rs_manifest_patcher::{Config, Location, Manifest, Transaction};
 
 #[cfg(target_os = "windows")]
 use std::io::Write;
@@ -23,31 +39,400 @@ async fn main() {
     }
 }
 
+/// A single installation to patch
+struct Target {
+    locations: Vec<Location>,
+    channel: Option<Channel>,
+    provider: ProviderChoice,
+    base_path: PathBuf,
+    /// Optional content groups to select
+    groups: Vec<String>,
+    /// Client locale, detected from the game directory if `None`
+    locale: Option<String>,
+    /// Keys the manifest signature is checked against
+    trust: TrustedKeys,
+}
+
+impl Target {
+    /// The installation described by the top-level options
+    fn from_config(config: &Config) -> Self {
+        Target {
+            locations: config.manifest_locations.clone(),
+            channel: config.channel.clone(),
+            provider: config.manifest_provider.clone(),
+            base_path: config.base_path.clone(),
+            groups: config.groups.clone(),
+            locale: config.locale.clone(),
+            trust: config.trust.clone(),
+        }
+    }
+}
+
+/// Result of patching a single installation
+enum Outcome {
+    UpToDate,
+    Updated { files: usize, size: u64 },
+    Declined,
+}
+
 async fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    banner::print_banner();
+    match &config.action {
+        Action::Patch | Action::Uninstall => {
+            banner::print_banner();
 
-    let base_path = std::env::current_dir().expect("Failed to get current directory");
-    let manifest = Manifest::build(&config.manifest_location).await?;
-    let transaction = Transaction::new(manifest, base_path);
+            let outcome = patch(&config, Target::from_config(&config)).await?;
+            if let Outcome::Declined = outcome {
+                process::exit(1);
+            }
 
-    transaction.print();
+            println!("\n{}", "-".repeat(100));
+            match (&config.action, outcome) {
+                (Action::Uninstall, Outcome::UpToDate) => {
+                    println!("No custom content to remove.")
+                }
+                (Action::Uninstall, _) => println!("Custom content was successfully removed."),
+                _ => println!("All files are up to date or successfully downloaded."),
+            }
+        }
+        Action::Export { path, all } => {
+            let client = http::build_client(&config.http)?;
+            let (transaction, provider) =
+                plan(&config, Target::from_config(&config), &client).await?;
+            transaction
+                .export(&client, print_progress, provider, path, *all)
+                .await?;
+            println!("\nExported the patch bundle to {}", path.display());
+            return Ok(());
+        }
+        Action::Mirror {
+            dir,
+            base_url,
+            signing_key,
+        } => {
+            let signing_key = signing_key
+                .as_deref()
+                .map(signature::load_signing_key)
+                .transpose()?;
+            let client = http::build_client(&config.http)?;
+            let options = BuildOptions {
+                client: client.clone(),
+                cache_path: None,
+                trust: config.trust.clone(),
+                stdin_signature: config.stdin_signature.clone(),
+                reset_baseline: config.reset_manifest_baseline,
+            };
+            let manifest = Manifest::build_any(&config.manifest_locations, &options).await?;
+            let provider =
+                select_provider(&client, &manifest, config.manifest_provider.clone()).await?;
+
+            std::fs::create_dir_all(dir)?;
+            let options = TransactionOptions {
+                quick_verify: !config.full_verify,
+                hard_link: config.hard_link,
+                cache: config.cache.clone(),
+                segments: config.segments,
+                segment_threshold: config.segment_threshold,
+                ..Default::default()
+            };
+            let transaction = mirror::plan(&manifest, dir, options)?;
+            transaction.print();
+            if transaction.has_pending_operations() {
+                transaction
+                    .download(&client, print_progress, provider.clone())
+                    .await?;
+            }
+            transaction.install_state(&provider).save(dir)?;
 
-    if transaction.has_pending_operations() {
-        if !prompt::confirm("Is this ok")? {
-            process::exit(1);
+            let path = mirror::write_manifest(&manifest, dir, base_url, signing_key.as_ref())?;
+            println!("\nWrote the mirror manifest to {}", path.display());
+            print_signing_note(signing_key.as_ref());
+            return Ok(());
         }
+        Action::Serve {
+            bind,
+            base_url,
+            signing_key,
+        } => {
+            let signing_key = signing_key
+                .as_deref()
+                .map(signature::load_signing_key)
+                .transpose()?;
+            let options = BuildOptions {
+                client: http::build_client(&config.http)?,
+                cache_path: Some(ManifestCache::default_path(&config.base_path)),
+                trust: config.trust.clone(),
+                stdin_signature: config.stdin_signature.clone(),
+                reset_baseline: config.reset_manifest_baseline,
+            };
+            let manifest = Manifest::build_any(&config.manifest_locations, &options).await?;
 
-        let progress_handler = |progress: &Progress| {
-            progress.print();
-            Ok(())
-        };
-        transaction
-            .download(progress_handler, config.manifest_provider)
-            .await?;
-    }
+            println!("Verifying files in {}...", config.base_path.display());
+            let base_path = config.base_path.clone();
+            let mut server =
+                tokio::task::spawn_blocking(move || FileServer::new(manifest, base_path)).await??;
+            server.base_url = base_url.clone();
+            server.signing_key = signing_key.clone();
+            if server.served_count() < server.file_count() {
+                println!(
+                    "{}",
+                    format!(
+                        "Warning: {} files are missing or do not match the manifest and are not served",
+                        server.file_count() - server.served_count()
+                    )
+                    .yellow()
+                );
+            }
 
-    println!("\n{}", "-".repeat(100));
-    println!("All files are up to date or successfully downloaded.");
+            let listener = tokio::net::TcpListener::bind(bind).await?;
+            println!(
+                "Serving {} files on {}, the manifest is at {}",
+                server.served_count(),
+                listener.local_addr()?,
+                match base_url {
+                    Some(base_url) =>
+                        mirror::file_url(base_url, mirror::MANIFEST_FILE)?.to_string(),
+                    None => format!(
+                        "http://<this machine>:{}/{}",
+                        bind.port(),
+                        mirror::MANIFEST_FILE
+                    ),
+                }
+            );
+            print_signing_note(signing_key.as_ref());
+            std::sync::Arc::new(server).serve(listener).await?;
+            return Ok(());
+        }
+        Action::Providers => {
+            let options = BuildOptions {
+                client: http::build_client(&config.http)?,
+                cache_path: None,
+                trust: config.trust.clone(),
+                stdin_signature: config.stdin_signature.clone(),
+                reset_baseline: config.reset_manifest_baseline,
+            };
+            let manifest = Manifest::build_any(&config.manifest_locations, &options).await?;
+            println!(
+                "Providers offered by {}:",
+                manifest.origin.location.as_ref().unwrap()
+            );
+            for provider in manifest.providers() {
+                let missing = manifest.files_without_provider(&provider).len();
+                print!(" {:<16} {}", provider.key(), provider.display_name());
+                if missing > 0 {
+                    print!(" {}", format!("(missing {missing} files)").yellow());
+                }
+                println!();
+            }
+            return Ok(());
+        }
+        Action::Status => {
+            match InstallState::load(&config.base_path)? {
+                Some(state) => {
+                    println!("Base path: {}", config.base_path.display());
+                    println!(" Version: {}", state.version);
+                    println!(" UID: {}", state.uid);
+                    if let Some(channel) = &state.channel {
+                        println!(" Channel: {channel}");
+                    }
+                    if let Some(source) = &state.source {
+                        println!(" Source: {source}");
+                    }
+                    if let Some(provider) = &state.provider {
+                        println!(" Provider: {}", provider.display_name());
+                    }
+                    println!(
+                        " Applied: {}",
+                        format::age_to_human_readable(
+                            format::unix_timestamp().saturating_sub(state.applied_at)
+                        )
+                    );
+                    println!(" Files: {}", state.files.len());
+                    if !state.groups.is_empty() {
+                        let groups: Vec<&str> = state.groups.iter().map(String::as_str).collect();
+                        println!(" Groups: {}", groups.join(", "));
+                    }
+                }
+                None => println!(
+                    "No manifest has been applied to {}",
+                    config.base_path.display()
+                ),
+            }
+            // The base client is not covered by the manifest
+            if !game::verify_game_integrity(&config.base_path)? {
+                println!(
+                    "{}",
+                    "The base client is incomplete, the patched game may not start".yellow()
+                );
+            }
+            return Ok(());
+        }
+        Action::CacheList | Action::CacheVerify | Action::CachePurge => {
+            let cache = config
+                .cache
+                .as_ref()
+                .ok_or("No download cache configured, pass --cache-dir or set PATCHER_CACHE_DIR")?;
+            match config.action {
+                Action::CacheList => {
+                    let entries = cache.entries()?;
+                    println!(
+                        "Download cache {}: {} files, {} of {}",
+                        cache.dir.display(),
+                        entries.len(),
+                        humansize::format_size(
+                            entries.iter().map(|entry| entry.size).sum::<u64>(),
+                            BINARY
+                        ),
+                        humansize::format_size(cache.max_size, BINARY)
+                    );
+                    let now = std::time::SystemTime::now();
+                    for entry in &entries {
+                        let age = now
+                            .duration_since(entry.last_used)
+                            .unwrap_or_default()
+                            .as_secs();
+                        println!(
+                            " {} {:>10}  used {}",
+                            entry.hash,
+                            humansize::format_size(entry.size, BINARY),
+                            format::age_to_human_readable(age)
+                        );
+                    }
+                }
+                Action::CacheVerify => {
+                    let corrupt = cache.verify()?;
+                    for entry in &corrupt {
+                        println!(" {}", format!("Removed corrupt entry {}", entry.hash).red());
+                    }
+                    println!(
+                        "Verified the download cache, {} corrupt files removed",
+                        corrupt.len()
+                    );
+                }
+                _ => {
+                    let removed = cache.purge()?;
+                    println!(
+                        "Removed {} files ({}) from the download cache",
+                        removed.len(),
+                        humansize::format_size(
+                            removed.iter().map(|entry| entry.size).sum::<u64>(),
+                            BINARY
+                        )
+                    );
+                }
+            }
+            return Ok(());
+        }
+        Action::ProfileList => {
+            let registry = ProfileRegistry::load(&config.profiles_path)?;
+            if registry.profiles.is_empty() {
+                println!(
+                    "No profiles registered in {}",
+                    config.profiles_path.display()
+                );
+            }
+            for profile in &registry.profiles {
+                println!("{}", profile.name.bold());
+                println!(" Base path: {}", profile.base_path.display());
+                println!(" Manifest: {}", profile.manifest);
+                for fallback in &profile.fallback_manifests {
+                    println!(" Fallback manifest: {fallback}");
+                }
+                if let Some(channel) = &profile.channel {
+                    println!(" Channel: {channel}");
+                }
+                println!(" Provider: {}", profile.provider.display_name());
+                if let Some(locale) = &profile.locale {
+                    println!(" Locale: {locale}");
+                }
+                if !profile.groups.is_empty() {
+                    println!(" Groups: {}", profile.groups.join(", "));
+                }
+            }
+            return Ok(());
+        }
+        Action::ProfileAdd { profile, replace } => {
+            let mut registry = ProfileRegistry::load(&config.profiles_path)?;
+            if *replace && registry.get(&profile.name).is_some() {
+                registry.replace(profile.clone())?;
+            } else {
+                registry.add(profile.clone())?;
+            }
+            registry.save(&config.profiles_path)?;
+            println!("Saved profile '{}'", profile.name);
+            return Ok(());
+        }
+        Action::ProfileRemove(name) => {
+            let mut registry = ProfileRegistry::load(&config.profiles_path)?;
+            registry.remove(name)?;
+            registry.save(&config.profiles_path)?;
+            println!("Removed profile '{name}'");
+            return Ok(());
+        }
+        Action::ProfileUpdate(names) => {
+            banner::print_banner();
+
+            let registry = ProfileRegistry::load(&config.profiles_path)?;
+            let profiles = registry.select(names)?;
+            if profiles.is_empty() {
+                println!(
+                    "No profiles registered in {}",
+                    config.profiles_path.display()
+                );
+                return Ok(());
+            }
+
+            let mut results = Vec::new();
+            for profile in profiles {
+                println!("\n{} {}", "Profile:".bold(), profile.name.bold());
+                let locations = profile
+                    .manifests()
+                    .map(|manifest| Location::parse(manifest.clone()))
+                    .collect::<Result<Vec<_>, _>>();
+                let result = match (locations, profile.trust(&config.trust)) {
+                    (Ok(locations), Ok(trust)) => {
+                        let target = Target {
+                            locations,
+                            channel: profile.channel.clone(),
+                            provider: ProviderChoice::Fixed(profile.provider.clone()),
+                            base_path: profile.base_path.clone(),
+                            groups: profile
+                                .groups
+                                .iter()
+                                .chain(&config.groups)
+                                .cloned()
+                                .collect(),
+                            locale: config.locale.clone().or_else(|| profile.locale.clone()),
+                            trust,
+                        };
+                        patch(&config, target).await
+                    }
+                    (Err(e), _) => Err(e.into()),
+                    (_, Err(e)) => Err(e.into()),
+                };
+                results.push((profile.name.clone(), result));
+            }
+
+            println!("\n{}", "-".repeat(100));
+            println!("Profile Summary:");
+            for (name, result) in &results {
+                match result {
+                    Ok(Outcome::UpToDate) => println!(" {}: {}", name, "up to date".green()),
+                    Ok(Outcome::Updated { files, size }) => println!(
+                        " {}: {}",
+                        name,
+                        format!(
+                            "updated {} files ({})",
+                            files,
+                            humansize::format_size(*size, BINARY)
+                        )
+                        .yellow()
+                    ),
+                    Ok(Outcome::Declined) => println!(" {}: skipped", name),
+                    Err(e) => println!(" {}: {}", name, format!("failed: {e}").red()),
+                }
+            }
+        }
+    }
 
     #[cfg(target_os = "windows")]
     {
@@ -59,3 +444,204 @@ async fn run(config: Config) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Print the transaction for one installation and download pending files after confirmation
+async fn patch(config: &Config, target: Target) -> Result<Outcome, Box<dyn Error>> {
+    let client = http::build_client(&config.http)?;
+    let (mut transaction, provider) = plan(config, target, &client).await?;
+
+    // Without a prompt, files that would need confirmation are kept and listed
+    let modified_files = transaction.generate_report().modified_files;
+    for path in transaction.modified_to_confirm() {
+        let action = if modified_files
+            .iter()
+            .any(|file| file.path == path && file.removed)
+        {
+            "delete"
+        } else {
+            "overwrite"
+        };
+        let overwrite = if config.assume_yes {
+            println!("Kept modified file {path}, run without --yes to {action} it");
+            false
+        } else {
+            prompt::confirm(&format!("{path} was modified, {action} it"))?
+        };
+        transaction.confirm_modified(&path, overwrite);
+    }
+
+    if !transaction.has_pending_operations() {
+        transaction
+            .install_state(&provider)
+            .save(&transaction.base_path)?;
+        return Ok(Outcome::UpToDate);
+    }
+
+    if !config.assume_yes && !prompt::confirm("Is this ok")? {
+        return Ok(Outcome::Declined);
+    }
+
+    transaction
+        .download(&client, print_progress, provider.clone())
+        .await?;
+    transaction
+        .install_state(&provider)
+        .save(&transaction.base_path)?;
+
+    Ok(Outcome::Updated {
+        files: transaction.pending_count() + transaction.removal_count(),
+        size: transaction.generate_report().total_download_size,
+    })
+}
+
+fn print_progress(progress: &Progress) -> Result<(), Box<dyn Error>> {
+    progress.print();
+    Ok(())
+}
+
+/// Tell how clients can trust a manifest rewritten by the mirror or serve command
+fn print_signing_note(signing_key: Option<&ed25519_dalek::SigningKey>) {
+    match signing_key {
+        Some(key) => println!(
+            "The manifest is signed, clients trust it with --trusted-key {}",
+            signature::public_key(key)
+        ),
+        None => println!(
+            "{}",
+            "Warning: the manifest is unsigned. Clients need --allow-unsigned to use it, or pass --signing-key to sign it"
+                .yellow()
+        ),
+    }
+}
+
+/// Resolve `auto` to the fastest provider of the manifest and check the provider is offered
+async fn select_provider(
+    client: &reqwest::Client,
+    manifest: &Manifest,
+    choice: ProviderChoice,
+) -> Result<Provider, Box<dyn Error>> {
+    let provider = match choice {
+        ProviderChoice::Fastest => {
+            let results = probe::probe_all(client, manifest).await;
+            let fastest = probe::fastest(&results).map(|result| result.provider.clone());
+            probe::print_results(&results, fastest.as_ref());
+            fastest.ok_or("No provider responded to the probe")?
+        }
+        ProviderChoice::Fixed(provider) => provider,
+    };
+    manifest.validate_provider(&provider)?;
+    Ok(provider)
+}
+
+/// Load the manifest for one installation, from the bundle if one is given, and print the
+/// planned transaction
+async fn plan(
+    config: &Config,
+    target: Target,
+    client: &reqwest::Client,
+) -> Result<(Transaction, Provider), Box<dyn Error>> {
+    let bundle = config.bundle.as_deref().map(Bundle::open).transpose()?;
+    let manifest = match &bundle {
+        Some(bundle) => bundle.manifest(&target.trust)?,
+        None => {
+            let options = BuildOptions {
+                client: client.clone(),
+                cache_path: Some(ManifestCache::default_path(&target.base_path)),
+                trust: target.trust.clone(),
+                stdin_signature: config.stdin_signature.clone(),
+                reset_baseline: config.reset_manifest_baseline,
+            };
+            Manifest::build_any(&target.locations, &options).await?
+        }
+    };
+
+    let provider = match target.provider {
+        // Bundles are used offline, there is nothing to probe
+        ProviderChoice::Fastest if bundle.is_some() => Provider::None,
+        choice => select_provider(client, &manifest, choice).await?,
+    };
+    if config.remember_provider {
+        probe::remember(&target.base_path, &provider)?;
+    }
+
+    let without_provider = manifest.files_without_provider(&provider);
+    if !without_provider.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "Warning: {} files do not offer provider '{}' and will fall back to '{}' where available:",
+                without_provider.len(),
+                provider.key(),
+                Provider::None.key()
+            )
+            .yellow()
+        );
+        for file in without_provider {
+            println!("  {}", file.path);
+        }
+    }
+
+    let mut ignore = IgnoreRules::load(&target.base_path)?;
+    ignore.extend(config.ignore.clone());
+
+    // Optional groups selected earlier stay selected until they are deselected
+    let state = InstallState::load(&target.base_path)?;
+    let mut groups: BTreeSet<String> = state
+        .as_ref()
+        .map(|state| state.groups.clone())
+        .unwrap_or_default();
+    for group in &target.groups {
+        if !manifest
+            .files
+            .iter()
+            .any(|file| file.optional_group() == Some(group.as_str()))
+        {
+            println!(
+                "{}",
+                format!("Warning: the manifest has no optional group '{group}'").yellow()
+            );
+        }
+        groups.insert(group.clone());
+    }
+    for group in &config.without_groups {
+        groups.remove(group);
+    }
+
+    let locale = target
+        .locale
+        .or_else(|| game::detect_locale(&target.base_path).map(String::from));
+    let locales = manifest.locales();
+    if !locales.is_empty() {
+        let warning = match &locale {
+            None => Some("Warning: the client locale could not be detected, locale-specific files are skipped. Use --locale to set it".to_string()),
+            Some(locale) if !locales.iter().any(|l| l.eq_ignore_ascii_case(locale)) => Some(format!(
+                "Warning: the manifest has no content for locale {locale} (available: {})",
+                locales.join(", ")
+            )),
+            Some(_) => None,
+        };
+        if let Some(warning) = warning {
+            println!("{}", warning.yellow());
+        }
+    }
+
+    let options = TransactionOptions {
+        state,
+        quick_verify: !config.full_verify,
+        modified_policy: config.modified_policy.clone(),
+        ignore,
+        custom: config.custom,
+        groups,
+        locale,
+        hard_link: config.hard_link,
+        cache: config.cache.clone(),
+        bundle,
+        segments: config.segments,
+        segment_threshold: config.segment_threshold,
+    };
+    let mut transaction = Transaction::with_options(manifest, target.base_path, options);
+    transaction.channel = target.channel;
+
+    transaction.print();
+    Ok((transaction, provider))
+}
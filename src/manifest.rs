@@ -1,11 +1,19 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::encoding::Encoding;
+use super::format::unix_timestamp;
+use super::manifest_cache::{CachedManifest, ManifestCache, SeenManifest};
+use super::signature::{SignatureStatus, TrustedKeys, SIGNATURE_EXTENSION};
+
 #[derive(Debug, Clone)]
 pub enum Location {
     Url(Url),
@@ -15,11 +23,17 @@ pub enum Location {
     /// It leverages the platform-specific features of [std::path::PathBuf]
     /// to provide a reliable method for handling file paths regardless of the operating system.
     FilePath(PathBuf),
+    /// Read the manifest from standard input, given as `-` on the command line.
+    Stdin,
 }
 
 impl Location {
     /// Parse a manifest location string into a `Location` enum
     pub fn parse(manifest_str: String) -> Result<Self, &'static str> {
+        if manifest_str == "-" {
+            return Ok(Location::Stdin);
+        }
+
         if let Ok(parsed_url) = Url::parse(&manifest_str) {
             // A relative URL string should return an error
             if parsed_url.cannot_be_a_base() {
@@ -29,6 +43,12 @@ impl Location {
             if parsed_url.scheme() == "http" || parsed_url.scheme() == "https" {
                 return Ok(Location::Url(parsed_url));
             }
+            if parsed_url.scheme() == "file" {
+                let path = parsed_url
+                    .to_file_path()
+                    .map_err(|_| "File URL must be an absolute local path")?;
+                return Self::parse(path.to_string_lossy().to_string());
+            }
         }
 
         let path = PathBuf::from(&manifest_str);
@@ -37,12 +57,22 @@ impl Location {
         }
 
         Err(
-            "Manifest location must be a valid URL (e.g., http://localhost:8080/manifest.json) \
-            or a readable file path",
+            "Manifest location must be a valid URL (e.g., http://localhost:8080/manifest.json), \
+            a readable file path or - for stdin",
         )
     }
 }
 
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Url(url) => write!(f, "{url}"),
+            Location::FilePath(path) => write!(f, "{}", path.display()),
+            Location::Stdin => write!(f, "<stdin>"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Provider {
@@ -94,6 +124,18 @@ impl FromStr for Provider {
     }
 }
 
+/// URL for a specific provider in the `Urls` of a file, delta, compressed copy or chunk, falling
+/// back to "none" if not found
+pub fn provider_url<'a>(
+    urls: &'a HashMap<Provider, String>,
+    provider: &Provider,
+) -> Option<&'a String> {
+    urls.get(provider).or_else(|| urls.get(&Provider::None))
+}
+
+/// Group of optional files that do not name one
+pub const DEFAULT_OPTIONAL_GROUP: &str = "optional";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 /// Represents a patch file with its associated metadata.
@@ -105,20 +147,121 @@ impl FromStr for Provider {
 /// - `size` - A 64-bit integer indicating the file size in bytes.
 /// * `custom` - A boolean flag that indicates if the patch file is custom.
 /// * `urls` - A map of provider names to their corresponding URLs.
+/// * `group` - Optional content group the file belongs to, e.g. `hd-textures`.
+/// * `optional` - Whether the file is only installed if its group is selected.
+/// * `locale` - Client locale the file belongs to, e.g. `enUS`. Files without one are installed for every locale.
+/// * `deltas` - Binary patches from earlier versions of the file to this one.
+/// * `compressed` - A compressed copy of the file to download instead of the raw file.
+/// * `chunks` - Ordered chunks covering the file, so only changed regions are downloaded.
 pub struct PatchFile {
     pub path: String,
     pub hash: String,
     pub size: i64,
     pub custom: bool,
     pub urls: HashMap<Provider, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deltas: Vec<Delta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed: Option<CompressedVariant>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// A region of a patch file that can be downloaded on its own.
+///
+/// # Fields
+///
+/// - `offset` - Position of the chunk in the file.
+/// - `size` - Size of the chunk in bytes.
+/// - `hash` - Hash of the chunk contents.
+/// - `urls` - URLs of the chunk as a separate file. If empty, the chunk is requested from the
+///   file's URL with a range request.
+pub struct Chunk {
+    pub offset: u64,
+    pub size: u64,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub urls: HashMap<Provider, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// A compressed copy of a patch file. The file's hash applies to the decompressed contents.
+///
+/// # Fields
+///
+/// - `encoding` - Compression format, `zstd` or `gzip`.
+/// - `size` - Compressed size in bytes.
+/// - `urls` - A map of provider names to the URLs of the compressed file.
+pub struct CompressedVariant {
+    pub encoding: Encoding,
+    pub size: i64,
+    pub urls: HashMap<Provider, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// A binary patch turning an earlier version of a file into the version in the manifest.
+///
+/// # Fields
+///
+/// - `source_hash` - Hash of the earlier version the delta applies to.
+/// - `size` - Size of the delta in bytes.
+/// - `urls` - A map of provider names to the URLs of the delta.
+pub struct Delta {
+    pub source_hash: String,
+    pub size: i64,
+    pub urls: HashMap<Provider, String>,
 }
 
 impl PatchFile {
+    /// Group selecting an optional file, optional files without a `Group` belong to
+    /// [`DEFAULT_OPTIONAL_GROUP`]. `None` for files that are always installed.
+    pub fn optional_group(&self) -> Option<&str> {
+        self.optional
+            .then(|| self.group.as_deref().unwrap_or(DEFAULT_OPTIONAL_GROUP))
+    }
+
+    /// Get the delta that applies to a local file with the given hash
+    pub fn delta_from(&self, hash: &str) -> Option<&Delta> {
+        self.deltas.iter().find(|delta| delta.source_hash == hash)
+    }
+
+    /// Indices of the chunks that differ from the local contents
+    pub fn stale_chunks(&self, contents: &[u8]) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| {
+                let range = chunk.offset as usize..(chunk.offset + chunk.size) as usize;
+                contents
+                    .get(range)
+                    .is_none_or(|local| format!("{:x}", md5::compute(local)) != chunk.hash)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Check whether the file belongs to the client locale, files without a `Locale` always do
+    pub fn is_for_locale(&self, locale: Option<&str>) -> bool {
+        match (&self.locale, locale) {
+            (None, _) => true,
+            (Some(file_locale), Some(locale)) => file_locale.eq_ignore_ascii_case(locale),
+            (Some(_), None) => false,
+        }
+    }
+
     /// Get URL for a specific provider, falling back to "none" if not found
     pub fn get_url(&self, provider: &Provider) -> Option<&String> {
-        self.urls
-            .get(provider)
-            .or_else(|| self.urls.get(&Provider::None))
+        provider_url(&self.urls, provider)
     }
 
     /// Get all available providers for this file
@@ -127,6 +270,109 @@ impl PatchFile {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+/// Options controlling how [`Manifest::build`] obtains a manifest
+pub struct BuildOptions {
+    pub client: reqwest::Client,
+    /// Cache file for manifests fetched over HTTP. Enables conditional requests and
+    /// falling back to the cached manifest when the network is unavailable.
+    pub cache_path: Option<PathBuf>,
+    /// Keys used to verify the manifest signature
+    pub trust: TrustedKeys,
+    /// Detached signature file for a manifest read from stdin, which has no location to find
+    /// its signature next to
+    pub stdin_signature: Option<PathBuf>,
+    /// Forget the newest manifest seen from the requested sources before comparing, e.g. after
+    /// the server republished a release under a new UID
+    pub reset_baseline: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Where a manifest was loaded from
+pub struct Origin {
+    pub location: Option<Location>,
+    /// Unix timestamp of the cached copy when the manifest could not be fetched and the
+    /// offline cache was used instead. The manifest may be out of date in that case.
+    pub stale_since: Option<u64>,
+    pub signature: SignatureStatus,
+    /// Sources tried before this one and why they were not used
+    pub skipped: Vec<String>,
+    /// Manifest JSON as loaded and its detached signature, kept so both can be exported and
+    /// verified again
+    pub contents: Option<String>,
+    pub raw_signature: Option<String>,
+}
+
+/// Compare dotted version strings, numerically where both parts are numbers
+///
+/// # Examples
+///
+/// ```
+/// use std::cmp::Ordering;
+/// use rs_manifest_patcher::manifest::compare_versions;
+/// assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+/// assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+/// assert_eq!(compare_versions("2.0", "2.0"), Ordering::Equal);
+/// ```
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// Whether `hash` is an MD5 hash written as 32 hexadecimal characters
+pub fn is_md5_hash(hash: &str) -> bool {
+    hash.len() == 32 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// MD5 of a file, read in blocks
+pub(crate) fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = md5::Context::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.consume(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.compute()))
+}
+
+/// Why `manifest` would roll back from the newest manifest seen from its sources, `None` if it
+/// may be used.
+///
+/// A manifest is a rollback if its version is older, or if it has the same version but a
+/// different UID, which means it is not the release that was accepted for that version.
+fn rollback_reason(manifest: &Manifest, newest: Option<&SeenManifest>) -> Option<String> {
+    let newest = newest?;
+    match compare_versions(&manifest.version, &newest.version) {
+        std::cmp::Ordering::Less => Some(format!(
+            "version {} is older than the previously seen version {}",
+            manifest.version, newest.version
+        )),
+        std::cmp::Ordering::Equal if manifest.uid != newest.uid => Some(format!(
+            "version {} was previously seen with UID {}, not {}",
+            manifest.version, newest.uid, manifest.uid
+        )),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 /// Represents a manifest configuration that includes version information
@@ -144,6 +390,8 @@ pub struct Manifest {
     pub uid: String,
     pub files: Vec<PatchFile>,
     pub removals: Option<Vec<String>>,
+    #[serde(skip)]
+    pub origin: Origin,
 }
 
 impl Manifest {
@@ -156,6 +404,33 @@ impl Manifest {
             .files
             .iter_mut()
             .for_each(|file| file.path = file.path.replace("\\", "/"));
+        // Hashes name files in the download cache and bundles, so they must not hold paths
+        if let Some(file) = manifest.files.iter().find(|file| !is_md5_hash(&file.hash)) {
+            return Err(format!(
+                "Invalid hash '{}' for {}, expected 32 hexadecimal characters",
+                file.hash, file.path
+            )
+            .into());
+        }
+        // Chunks are read and written at their offsets, so they must lie within their file
+        for file in &manifest.files {
+            let size = u64::try_from(file.size).unwrap_or(0);
+            let invalid = file.chunks.iter().find(|chunk| {
+                chunk.size == 0
+                    || chunk
+                        .offset
+                        .checked_add(chunk.size)
+                        .is_none_or(|end| end > size)
+            });
+            if let Some(chunk) = invalid {
+                return Err(format!(
+                    "Invalid chunk of {} bytes at offset {} for {}, chunks must not be empty or extend past the file",
+                    chunk.size, chunk.offset, file.path
+                )
+                .into());
+            }
+        }
+        manifest.origin.contents = Some(json.to_string());
 
         Ok(manifest)
     }
@@ -166,15 +441,302 @@ impl Manifest {
         Self::from_json(&contents)
     }
 
+    /// Get every provider offered by at least one file, sorted by key
+    pub fn providers(&self) -> Vec<Provider> {
+        let mut providers: Vec<Provider> = Vec::new();
+        for provider in self.files.iter().flat_map(|file| file.urls.keys()) {
+            if !providers.contains(provider) {
+                providers.push(provider.clone());
+            }
+        }
+        providers.sort_by(|a, b| a.key().cmp(b.key()));
+        providers
+    }
+
+    /// Get the locales the manifest has locale-specific files for
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<&str> = self
+            .files
+            .iter()
+            .filter_map(|file| file.locale.as_deref())
+            .collect();
+        locales.sort();
+        locales.dedup();
+        locales
+    }
+
+    /// Get files that do not list a URL for the given provider
+    pub fn files_without_provider(&self, provider: &Provider) -> Vec<&PatchFile> {
+        self.files
+            .iter()
+            .filter(|file| !file.urls.contains_key(provider))
+            .collect()
+    }
+
+    /// Check that a provider is offered by the manifest
+    pub fn validate_provider(&self, provider: &Provider) -> Result<(), String> {
+        let providers = self.providers();
+        if providers.contains(provider) {
+            return Ok(());
+        }
+        let available = providers
+            .iter()
+            .map(|p| format!("{} ({})", p.key(), p.display_name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(format!(
+            "Provider '{}' is not offered by the manifest. Available providers: {available}",
+            provider.key()
+        ))
+    }
+
     /// Build manifest from a location (URL or file)
-    pub async fn build(location: &Location) -> Result<Self, Box<dyn Error>> {
-        match location {
-            Location::Url(url) => {
-                let response = reqwest::get(url.as_str()).await?;
-                let contents = response.text().await?;
-                Self::from_json(&contents)
+    ///
+    /// The manifest signature is checked against the trusted keys in `options` before
+    /// the manifest is parsed. With a cache, a manifest older than the newest one seen
+    /// from this location is rejected, see [`Manifest::build_any`].
+    pub async fn build(
+        location: &Location,
+        options: &BuildOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (manifest, cache_entry) = Self::load(location, options).await?;
+        if let Some(cache_path) = &options.cache_path {
+            let sources = [location.to_string()];
+            let mut cache = Self::load_cache(cache_path, &sources, options);
+            if let Some(reason) = rollback_reason(&manifest, cache.newest(&sources)) {
+                return Err(reason.into());
+            }
+            Self::accept(&manifest, &sources, cache_entry, &mut cache, cache_path);
+        }
+        Ok(manifest)
+    }
+
+    /// Build a manifest from the first of several locations that succeeds and validates.
+    ///
+    /// Locations are tried in order. With a cache, every manifest is compared with the
+    /// newest manifest accepted so far from the same list of locations: an older version, or
+    /// the same version with a different UID, is rejected so an outdated or tampered mirror
+    /// cannot roll back an installation. Other lists, such as another channel, have their own
+    /// baseline. A cached copy used because its location was unreachable
+    /// is only returned when no other location responds.
+    pub async fn build_any(
+        locations: &[Location],
+        options: &BuildOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let sources: Vec<String> = locations.iter().map(Location::to_string).collect();
+        let mut cache = options
+            .cache_path
+            .as_deref()
+            .map(|cache_path| Self::load_cache(cache_path, &sources, options))
+            .unwrap_or_default();
+
+        let mut skipped = Vec::new();
+        let mut stale = None;
+        for location in locations {
+            let (mut manifest, cache_entry) = match Self::load(location, options).await {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    skipped.push(format!("{location}: {e}"));
+                    continue;
+                }
+            };
+            if let Some(reason) = rollback_reason(&manifest, cache.newest(&sources)) {
+                skipped.push(format!("{location}: {reason}"));
+                continue;
+            }
+            if manifest.origin.stale_since.is_some() {
+                skipped.push(format!("{location}: unreachable"));
+                stale.get_or_insert(manifest);
+                continue;
+            }
+            if let Some(cache_path) = &options.cache_path {
+                Self::accept(&manifest, &sources, cache_entry, &mut cache, cache_path);
             }
-            Location::FilePath(file_path) => Self::from_file(file_path),
+            manifest.origin.skipped = skipped;
+            return Ok(manifest);
         }
+
+        match stale {
+            Some(mut manifest) => {
+                if let Some(cache_path) = &options.cache_path {
+                    Self::accept(&manifest, &sources, None, &mut cache, cache_path);
+                }
+                manifest.origin.skipped = skipped;
+                Ok(manifest)
+            }
+            None => Err(format!(
+                "No manifest source could be used:\n  {}",
+                skipped.join("\n  ")
+            )
+            .into()),
+        }
+    }
+
+    /// Fetch, verify and parse a manifest without touching the cache. Returns the cache entry
+    /// to store if the manifest is accepted.
+    async fn load(
+        location: &Location,
+        options: &BuildOptions,
+    ) -> Result<(Self, Option<CachedManifest>), Box<dyn Error>> {
+        let fetched = match location {
+            Location::Url(url) => Self::fetch(url, options).await?,
+            Location::FilePath(file_path) => {
+                let mut signature_path = file_path.clone().into_os_string();
+                signature_path.push(SIGNATURE_EXTENSION);
+                Fetched {
+                    contents: std::fs::read_to_string(file_path)?,
+                    signature: std::fs::read_to_string(signature_path).ok(),
+                    stale_since: None,
+                    cache_entry: None,
+                }
+            }
+            Location::Stdin => {
+                let mut contents = String::new();
+                tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut contents)
+                    .await?;
+                Fetched {
+                    contents,
+                    signature: options
+                        .stdin_signature
+                        .as_ref()
+                        .map(std::fs::read_to_string)
+                        .transpose()?,
+                    stale_since: None,
+                    cache_entry: None,
+                }
+            }
+        };
+
+        let signature = options
+            .trust
+            .verify(fetched.contents.as_bytes(), fetched.signature.as_deref())?;
+        let mut manifest = Self::from_json(&fetched.contents)?;
+        manifest.origin = Origin {
+            location: Some(location.clone()),
+            stale_since: fetched.stale_since,
+            signature,
+            skipped: Vec::new(),
+            contents: Some(fetched.contents),
+            raw_signature: fetched.signature,
+        };
+        Ok((manifest, fetched.cache_entry))
     }
+
+    /// Load the manifest cache, forgetting the newest manifest of `sources` if requested
+    fn load_cache(cache_path: &Path, sources: &[String], options: &BuildOptions) -> ManifestCache {
+        let mut cache = ManifestCache::load(cache_path);
+        if options.reset_baseline {
+            cache.forget_newest(sources);
+        }
+        cache
+    }
+
+    /// Record an accepted manifest as the newest seen from `sources` and cache its contents
+    fn accept(
+        manifest: &Self,
+        sources: &[String],
+        cache_entry: Option<CachedManifest>,
+        cache: &mut ManifestCache,
+        cache_path: &Path,
+    ) {
+        cache.set_newest(SeenManifest {
+            sources: sources.to_vec(),
+            uid: manifest.uid.clone(),
+            version: manifest.version.clone(),
+        });
+        if let Some(cache_entry) = cache_entry {
+            cache.insert(cache_entry);
+        }
+        // The cache is an optimization, failing to write it must not fail the run
+        let _ = cache.save(cache_path);
+    }
+
+    /// Fetch a manifest over HTTP, revalidating and falling back to the offline cache if configured
+    async fn fetch(url: &Url, options: &BuildOptions) -> Result<Fetched, Box<dyn Error>> {
+        let cached = options
+            .cache_path
+            .as_deref()
+            .map(ManifestCache::load)
+            .and_then(|cache| cache.entry(url.as_str()).cloned());
+
+        let mut request = options.client.get(url.as_str());
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) if response.status().is_server_error() => {
+                response.error_for_status().map_err(Into::into)
+            }
+            result => result.map_err(Box::<dyn Error>::from),
+        };
+        let response = match response {
+            Ok(response) => response,
+            // Network unavailable: use the cached manifest, flagged as possibly stale
+            Err(e) => match cached {
+                Some(cached) => {
+                    return Ok(Fetched {
+                        contents: cached.contents,
+                        signature: cached.signature,
+                        stale_since: Some(cached.fetched_at),
+                        cache_entry: None,
+                    })
+                }
+                None => return Err(e),
+            },
+        };
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        let contents = match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(cached)) => cached.contents,
+            _ => response.error_for_status()?.text().await?,
+        };
+        let signature = Self::fetch_signature(url, &options.client).await;
+
+        Ok(Fetched {
+            cache_entry: Some(CachedManifest {
+                location: url.to_string(),
+                etag,
+                last_modified,
+                fetched_at: unix_timestamp(),
+                contents: contents.clone(),
+                signature: signature.clone(),
+            }),
+            contents,
+            signature,
+            stale_since: None,
+        })
+    }
+
+    /// Fetch the detached signature stored next to the manifest, `None` if there is none
+    async fn fetch_signature(url: &Url, client: &reqwest::Client) -> Option<String> {
+        let mut signature_url = url.clone();
+        signature_url.set_path(&format!("{}{SIGNATURE_EXTENSION}", url.path()));
+
+        let response = client.get(signature_url).send().await.ok()?;
+        response.error_for_status().ok()?.text().await.ok()
+    }
+}
+
+/// Raw manifest contents as obtained from a location
+struct Fetched {
+    contents: String,
+    signature: Option<String>,
+    stale_since: Option<u64>,
+    /// Cache entry to store once the contents have been validated
+    cache_entry: Option<CachedManifest>,
 }
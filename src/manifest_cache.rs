@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::atomic_file;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// Last manifest fetched over HTTP from one location, kept for conditional requests and offline use.
+///
+/// # Fields
+///
+/// - `location` - URL the manifest was fetched from.
+/// - `etag` - `ETag` response header, sent back as `If-None-Match`.
+/// - `last_modified` - `Last-Modified` response header, sent back as `If-Modified-Since`.
+/// - `fetched_at` - Unix timestamp of the last successful fetch or revalidation.
+/// - `contents` - Raw manifest JSON.
+/// - `signature` - Detached signature fetched with the manifest, if any.
+pub struct CachedManifest {
+    pub location: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: u64,
+    pub contents: String,
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// Newest manifest accepted for an installation from one list of sources. Older manifests, or
+/// a different manifest with the same version, are rejected from those sources so a mirror
+/// cannot roll back the installation.
+///
+/// # Fields
+///
+/// - `sources` - Manifest locations the manifest was requested from, in order. Each channel or
+///   `--manifest` list has its own baseline, so switching channels is not a rollback.
+/// - `uid` - UID of the accepted manifest.
+/// - `version` - Version of the accepted manifest.
+pub struct SeenManifest {
+    pub sources: Vec<String>,
+    pub uid: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// Manifest cache of an installation: the newest manifest seen from every list of sources and
+/// the last manifest of every location fetched over HTTP.
+pub struct ManifestCache {
+    #[serde(default)]
+    pub seen: Vec<SeenManifest>,
+    #[serde(default)]
+    pub entries: Vec<CachedManifest>,
+}
+
+impl ManifestCache {
+    /// Default cache file for an installation
+    pub fn default_path(base_path: &Path) -> PathBuf {
+        super::config::data_dir(base_path).join("manifest-cache.json")
+    }
+
+    /// Load the cache. Missing or unreadable caches are treated as empty.
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the cache file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        atomic_file::write(path, serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// Newest manifest accepted from a list of sources
+    pub fn newest(&self, sources: &[String]) -> Option<&SeenManifest> {
+        self.seen.iter().find(|seen| seen.sources == sources)
+    }
+
+    /// Record the newest manifest accepted from its list of sources
+    pub fn set_newest(&mut self, newest: SeenManifest) {
+        self.forget_newest(&newest.sources);
+        self.seen.push(newest);
+    }
+
+    /// Forget the newest manifest of a list of sources, so any version is accepted again
+    pub fn forget_newest(&mut self, sources: &[String]) {
+        self.seen.retain(|seen| seen.sources != sources);
+    }
+
+    /// Cached manifest of a location
+    pub fn entry(&self, location: &str) -> Option<&CachedManifest> {
+        self.entries.iter().find(|entry| entry.location == location)
+    }
+
+    /// Add or replace the cached manifest of a location
+    pub fn insert(&mut self, entry: CachedManifest) {
+        self.entries
+            .retain(|cached| cached.location != entry.location);
+        self.entries.push(entry);
+    }
+}
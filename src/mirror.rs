@@ -0,0 +1,117 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::SigningKey;
+use url::Url;
+
+use super::atomic_file;
+use super::manifest::{Manifest, Origin, PatchFile, Provider};
+use super::signature::{self, SIGNATURE_EXTENSION};
+use super::state::InstallState;
+use super::transaction::{CustomMode, Transaction, TransactionOptions};
+
+/// Name of the rewritten manifest inside the mirror directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Plan downloading every file of the manifest into `dir`, laid out by path.
+///
+/// Optional groups, locales and custom content are all included. The selection fields of
+/// `options` are overridden, the state of the previous run is loaded from `dir` so files that
+/// are already up to date are skipped.
+pub fn plan(
+    manifest: &Manifest,
+    dir: &Path,
+    options: TransactionOptions,
+) -> Result<Transaction, Box<dyn Error>> {
+    let mut planned = manifest.clone();
+    // A mirror serves clients of every locale
+    for file in &mut planned.files {
+        file.locale = None;
+    }
+    let options = TransactionOptions {
+        state: InstallState::load(dir)?,
+        custom: CustomMode::Install,
+        groups: manifest
+            .files
+            .iter()
+            .filter_map(|file| file.optional_group())
+            .map(String::from)
+            .collect(),
+        locale: None,
+        ..options
+    };
+    Ok(Transaction::with_options(
+        planned,
+        dir.to_path_buf(),
+        options,
+    ))
+}
+
+/// Copy of the manifest whose files are downloaded from the mirror at `base_url`
+pub fn rewrite(manifest: &Manifest, base_url: &Url) -> Result<Manifest, Box<dyn Error>> {
+    let mut rewritten = manifest.clone();
+    for file in &mut rewritten.files {
+        rewrite_file(file, base_url)?;
+    }
+    rewritten.origin = Origin::default();
+    Ok(rewritten)
+}
+
+/// Point a file at its copy below `base_url`.
+///
+/// Every provider of the file points at the copy, so clients keep working whatever provider
+/// they selected. Only whole files are copied: deltas and compressed copies are dropped,
+/// chunks are kept and requested by range from the file URL.
+pub fn rewrite_file(file: &mut PatchFile, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let url = file_url(base_url, &file.path)?.to_string();
+    file.urls.insert(Provider::None, String::new());
+    for file_url in file.urls.values_mut() {
+        file_url.clone_from(&url);
+    }
+    file.deltas.clear();
+    file.compressed = None;
+    for chunk in &mut file.chunks {
+        chunk.urls.clear();
+    }
+    Ok(())
+}
+
+/// Write the rewritten manifest to [`MANIFEST_FILE`] in the mirror directory and return its path.
+///
+/// With a signing key, its detached signature is written next to it. Otherwise a signature left
+/// by an earlier run is removed, it would not match the new manifest.
+pub fn write_manifest(
+    manifest: &Manifest,
+    dir: &Path,
+    base_url: &Url,
+    signing_key: Option<&SigningKey>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let path = dir.join(MANIFEST_FILE);
+    let contents = serde_json::to_string_pretty(&rewrite(manifest, base_url)?)?;
+    let mut signature_path = path.clone().into_os_string();
+    signature_path.push(SIGNATURE_EXTENSION);
+    let signature_path = PathBuf::from(signature_path);
+    match signing_key {
+        Some(key) => atomic_file::write(
+            &signature_path,
+            signature::sign(key, contents.as_bytes()).as_bytes(),
+        )?,
+        None => match std::fs::remove_file(&signature_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        },
+    }
+    atomic_file::write(&path, contents.as_bytes())?;
+    Ok(path)
+}
+
+/// URL of a manifest path below `base_url`, with every path segment percent-encoded
+pub fn file_url(base_url: &Url, path: &str) -> Result<Url, &'static str> {
+    let mut url = base_url.clone();
+    url.path_segments_mut()
+        .map_err(|_| "The mirror base URL must be an http(s) URL")?
+        .pop_if_empty()
+        .extend(path.split('/'));
+    Ok(url)
+}
@@ -0,0 +1,87 @@
+/// A gitignore-style path pattern.
+///
+/// - `*` matches anything except `/`, `?` matches a single character except `/`.
+/// - `**` as a whole segment matches any number of directories.
+/// - A pattern without `/` matches the file or directory name at any depth.
+/// - A pattern containing `/` is relative to the game directory, a leading `/` is optional.
+/// - A pattern matching a directory also matches everything below it.
+/// - A trailing `/` only matches directories.
+///
+/// Matching ignores ASCII case, as the game client runs on case-insensitive file systems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    segments: Vec<String>,
+    directory: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim().replace('\\', "/");
+        let trimmed = pattern.trim_end_matches('/');
+        if trimmed.is_empty() {
+            return Err(format!("Invalid empty pattern '{pattern}'"));
+        }
+
+        let anchored = trimmed.contains('/');
+        let mut segments: Vec<String> = trimmed
+            .trim_start_matches('/')
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_ascii_lowercase())
+            .collect();
+        if !anchored {
+            segments.insert(0, "**".to_string());
+        }
+
+        Ok(Pattern {
+            segments,
+            directory: pattern.ends_with('/'),
+        })
+    }
+
+    /// Check whether a relative path, or one of its parent directories, matches the pattern
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_manifest_patcher::pattern::Pattern;
+    /// let pattern = Pattern::new("Interface/AddOns").unwrap();
+    /// assert!(pattern.matches("Interface/AddOns/MyAddon/MyAddon.toc"));
+    /// assert!(!pattern.matches("Data/Interface/AddOns/file.txt"));
+    ///
+    /// let pattern = Pattern::new("*.wtf").unwrap();
+    /// assert!(pattern.matches("WTF/Config.wtf"));
+    /// ```
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.replace('\\', "/").to_ascii_lowercase();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        // Directory patterns never match the path itself, only its parents
+        let max_len = segments.len().saturating_sub(usize::from(self.directory));
+        (1..=max_len).any(|len| match_segments(&self.segments, &segments[..len]))
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                match_wildcard(first.as_bytes(), segment.as_bytes())
+                    && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_wildcard(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| match_wildcard(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && match_wildcard(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && match_wildcard(rest, &text[1..]),
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::pattern::Pattern;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// What to do with a file the user changed since it was installed
+pub enum ModifiedPolicy {
+    /// Replace the file with the manifest version
+    #[default]
+    Overwrite,
+    /// Leave the user's version in place
+    Keep,
+    /// Copy the user's version to the backup directory, then replace it
+    Backup,
+    /// Ask before replacing the file
+    Ask,
+}
+
+impl ModifiedPolicy {
+    /// Get all policy keys for CLI validation
+    pub fn known_keys() -> Vec<&'static str> {
+        vec!["overwrite", "keep", "backup", "ask"]
+    }
+
+    /// Describe the action taken for a modified file
+    pub fn description(&self) -> &str {
+        match self {
+            ModifiedPolicy::Overwrite => "will be overwritten",
+            ModifiedPolicy::Keep => "kept",
+            ModifiedPolicy::Backup => "will be backed up and overwritten",
+            ModifiedPolicy::Ask => "ask before overwriting",
+        }
+    }
+
+    /// Describe the action taken for a modified file that is being removed
+    pub fn removal_description(&self) -> &str {
+        match self {
+            ModifiedPolicy::Overwrite => "will be deleted",
+            ModifiedPolicy::Keep => "kept",
+            ModifiedPolicy::Backup => "will be backed up and deleted",
+            ModifiedPolicy::Ask => "ask before deleting",
+        }
+    }
+}
+
+impl FromStr for ModifiedPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(ModifiedPolicy::Overwrite),
+            "keep" => Ok(ModifiedPolicy::Keep),
+            "backup" => Ok(ModifiedPolicy::Backup),
+            "ask" => Ok(ModifiedPolicy::Ask),
+            other => Err(format!(
+                "Unknown policy '{other}', expected one of: {}",
+                Self::known_keys().join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Policies for user-modified files by path pattern. The last matching rule wins.
+pub struct PolicyRules {
+    rules: Vec<(Pattern, ModifiedPolicy)>,
+    /// Policy for files that match no rule
+    pub default: ModifiedPolicy,
+}
+
+impl PolicyRules {
+    /// Add a rule given as `PATTERN=POLICY`, e.g. `Interface/AddOns=keep`
+    pub fn add(&mut self, rule: &str) -> Result<(), String> {
+        let (pattern, policy) = rule
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Invalid rule '{rule}', expected PATTERN=POLICY"))?;
+        self.rules.push((
+            Pattern::new(pattern)?,
+            ModifiedPolicy::from_str(policy.trim())?,
+        ));
+        Ok(())
+    }
+
+    /// Get the policy for a manifest path
+    pub fn policy_for(&self, path: &str) -> ModifiedPolicy {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.matches(path))
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
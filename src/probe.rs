@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use futures::StreamExt;
+use humansize::DECIMAL;
+
+use super::manifest::{Manifest, Provider};
+use super::transport::{Source, TransportError};
+
+/// Number of bytes requested from each provider when probing
+pub const PROBE_SIZE: u64 = 256 * 1024;
+
+/// File inside the patcher data directory that stores the remembered provider
+const REMEMBERED_PROVIDER_FILE: &str = "provider";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Provider requested on the command line. `auto` is not a manifest provider, it selects the
+/// fastest provider of the manifest by probing.
+pub enum ProviderChoice {
+    Fixed(Provider),
+    Fastest,
+}
+
+impl FromStr for ProviderChoice {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => ProviderChoice::Fastest,
+            other => ProviderChoice::Fixed(Provider::from_str(other)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Latency and throughput measured for a single provider
+pub struct Measurement {
+    /// Time until the response headers were received
+    pub latency: Duration,
+    /// Number of body bytes received
+    pub bytes: u64,
+    /// Bytes per second over the whole request, including latency
+    pub throughput: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeResult {
+    pub provider: Provider,
+    pub outcome: Result<Measurement, String>,
+}
+
+/// Probe a single provider with a ranged request for the first file that offers it
+pub async fn probe(
+    client: &reqwest::Client,
+    manifest: &Manifest,
+    provider: &Provider,
+) -> ProbeResult {
+    let outcome = match manifest
+        .files
+        .iter()
+        .find(|file| file.size > 0 && file.urls.contains_key(provider))
+    {
+        // URLs are resolved like for downloads, so relative and file URLs can be probed too
+        Some(file) => {
+            match Source::resolve(&file.urls[provider], manifest.origin.location.as_ref()) {
+                Ok(source) => measure(client, &source, PROBE_SIZE.min(file.size as u64)).await,
+                Err(e) => Err(e),
+            }
+        }
+        None => Err("No file offers this provider".to_string()),
+    };
+    ProbeResult {
+        provider: provider.clone(),
+        outcome,
+    }
+}
+
+/// Probe every provider offered by the manifest, one after another so they do not compete for bandwidth
+pub async fn probe_all(client: &reqwest::Client, manifest: &Manifest) -> Vec<ProbeResult> {
+    let mut results = Vec::new();
+    for provider in manifest.providers() {
+        results.push(probe(client, manifest, &provider).await);
+    }
+    results
+}
+
+/// Select the successful probe with the highest throughput
+pub fn fastest(results: &[ProbeResult]) -> Option<&ProbeResult> {
+    results
+        .iter()
+        .filter(|result| result.outcome.is_ok())
+        .max_by(|a, b| {
+            let a = a.outcome.as_ref().unwrap().throughput;
+            let b = b.outcome.as_ref().unwrap().throughput;
+            a.total_cmp(&b)
+        })
+}
+
+async fn measure(
+    client: &reqwest::Client,
+    source: &Source,
+    size: u64,
+) -> Result<Measurement, String> {
+    let start = Instant::now();
+    // Servers without range support send the whole file, stop once enough was read
+    let mut stream = match source.open_range(client, 0, size).await {
+        Ok(stream) => stream,
+        Err(TransportError::Unavailable(_)) => {
+            source.open(client).await.map_err(|e| e.to_string())?
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let latency = start.elapsed();
+
+    let mut bytes: u64 = 0;
+    while let Some(chunk) = stream.next().await {
+        bytes += chunk.map_err(|e| e.to_string())?.len() as u64;
+        if bytes >= size {
+            break;
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    Ok(Measurement {
+        latency,
+        bytes,
+        throughput: if elapsed > 0.0 {
+            bytes as f64 / elapsed
+        } else {
+            0.0
+        },
+    })
+}
+
+/// Print probe results as a table, marking the selected provider
+pub fn print_results(results: &[ProbeResult], selected: Option<&Provider>) {
+    println!("\nProvider probe results:");
+    for result in results {
+        let marker = if Some(&result.provider) == selected {
+            "*"
+        } else {
+            " "
+        };
+        let name = format!(
+            "{} ({})",
+            result.provider.key(),
+            result.provider.display_name()
+        );
+        match &result.outcome {
+            Ok(measurement) => {
+                let line = format!(
+                    " {marker} {:<32} latency {:>6} ms | {:>10}/s",
+                    name,
+                    measurement.latency.as_millis(),
+                    humansize::format_size(measurement.throughput as u64, DECIMAL)
+                );
+                if Some(&result.provider) == selected {
+                    println!("{}", line.green());
+                } else {
+                    println!("{line}");
+                }
+            }
+            Err(e) => println!("{}", format!(" {marker} {name:<32} failed: {e}").red()),
+        }
+    }
+}
+
+fn remembered_provider_path(base_path: &Path) -> PathBuf {
+    super::config::data_dir(base_path).join(REMEMBERED_PROVIDER_FILE)
+}
+
+/// Load the provider remembered for an installation, if any
+pub fn load_remembered(base_path: &Path) -> Option<Provider> {
+    let contents = std::fs::read_to_string(remembered_provider_path(base_path)).ok()?;
+    let key = contents.trim();
+    if key.is_empty() {
+        return None;
+    }
+    key.parse().ok()
+}
+
+/// Remember a provider for future runs against the same installation
+pub fn remember(base_path: &Path, provider: &Provider) -> std::io::Result<()> {
+    let path = remembered_provider_path(base_path);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, provider.key())
+}
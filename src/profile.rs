@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::atomic_file;
+use super::channel::Channel;
+use super::manifest::Provider;
+use super::signature::TrustedKeys;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+/// A named game installation managed by the patcher.
+///
+/// # Fields
+///
+/// - `name` - Unique name used to refer to the profile on the command line.
+/// - `base_path` - Root directory of the game installation.
+/// - `manifest` - Manifest location (URL or file path) as given by the user.
+/// - `fallback_manifests` - Additional manifest locations tried in order when `manifest` fails.
+/// - `provider` - Provider used for downloads.
+/// - `channel` - Release channel the manifest location was built from, if any.
+/// - `groups` - Optional content groups to install in addition to the remembered selection.
+/// - `locale` - Client locale, detected from the game directory if not set.
+/// - `trusted_keys` - Base64 encoded public keys trusted for this profile's manifest signatures.
+/// - `allow_unsigned` - Accept unsigned or badly signed manifests for this profile.
+pub struct Profile {
+    pub name: String,
+    pub base_path: PathBuf,
+    pub manifest: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_manifests: Vec<String>,
+    pub provider: Provider,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_unsigned: bool,
+}
+
+impl Profile {
+    /// All manifest locations of the profile in order of preference
+    pub fn manifests(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.manifest).chain(&self.fallback_manifests)
+    }
+
+    /// `trust` extended with the keys and settings of the profile
+    pub fn trust(&self, trust: &TrustedKeys) -> Result<TrustedKeys, &'static str> {
+        let mut trust = trust.clone();
+        for key in &self.trusted_keys {
+            trust.add_key(key)?;
+        }
+        trust.allow_unsigned |= self.allow_unsigned;
+        Ok(trust)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// Collection of profiles persisted as JSON.
+pub struct ProfileRegistry {
+    pub profiles: Vec<Profile>,
+}
+
+impl ProfileRegistry {
+    /// Default location of the registry file inside the patcher configuration directory
+    pub fn default_path() -> Option<PathBuf> {
+        super::config::config_dir().map(|dir| dir.join("profiles.json"))
+    }
+
+    /// Load the registry from a file. A missing file yields an empty registry.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Write the registry to a file, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = serde_json::to_string_pretty(self)?;
+        atomic_file::write(path, contents.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Add a new profile. Fails if a profile with the same name already exists.
+    pub fn add(&mut self, profile: Profile) -> Result<(), String> {
+        if self.get(&profile.name).is_some() {
+            return Err(format!("Profile '{}' already exists", profile.name));
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Replace an existing profile with the same name
+    pub fn replace(&mut self, profile: Profile) -> Result<(), String> {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => {
+                *existing = profile;
+                Ok(())
+            }
+            None => Err(format!("Profile '{}' does not exist", profile.name)),
+        }
+    }
+
+    /// Remove a profile by name and return it
+    pub fn remove(&mut self, name: &str) -> Result<Profile, String> {
+        match self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)
+        {
+            Some(idx) => Ok(self.profiles.remove(idx)),
+            None => Err(format!("Profile '{name}' does not exist")),
+        }
+    }
+
+    /// Resolve a list of profile names, or all profiles if `names` is empty
+    pub fn select(&self, names: &[String]) -> Result<Vec<&Profile>, String> {
+        if names.is_empty() {
+            return Ok(self.profiles.iter().collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                self.get(name)
+                    .ok_or_else(|| format!("Profile '{name}' does not exist"))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ed25519_dalek::SigningKey;
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+use super::manifest::{hash_file, Manifest, Origin};
+use super::mirror::{self, MANIFEST_FILE};
+use super::signature::{self, SIGNATURE_EXTENSION};
+
+/// Serves the files of an installation that match the manifest, for other patchers on the LAN.
+///
+/// Files are served at their manifest path with `Range` support. The manifest is served at
+/// `/manifest.json` with the served files pointing at this server; files that could not be
+/// verified keep their original URLs. With a signing key, its signature is served at
+/// `/manifest.json.sig`.
+pub struct FileServer {
+    manifest: Manifest,
+    base_path: PathBuf,
+    /// Sizes of the verified files by manifest path
+    files: HashMap<String, u64>,
+    /// URL the server is reached at, taken from the `Host` header of each request if `None`
+    pub base_url: Option<Url>,
+    /// Key signing the served manifest, which is served unsigned if `None`
+    pub signing_key: Option<SigningKey>,
+    /// Time a client has to send a complete request, idle connections are closed after it
+    pub read_timeout: Duration,
+}
+
+/// Default time a client has to send a complete request
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest request or header line accepted
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+
+/// Most headers accepted in one request
+const MAX_HEADERS: usize = 100;
+
+/// Parsed request line and headers, header names are lowercased
+struct Request {
+    method: String,
+    target: String,
+    headers: HashMap<String, String>,
+}
+
+/// Part of a file selected by a `Range` header
+enum Range {
+    Full,
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+impl FileServer {
+    /// Hash every file of the manifest in `base_path` and keep the ones that match.
+    ///
+    /// This reads every file, so call it from a blocking context.
+    pub fn new(manifest: Manifest, base_path: PathBuf) -> io::Result<Self> {
+        let mut files = HashMap::new();
+        for file in &manifest.files {
+            let path = base_path.join(&file.path);
+            match hash_file(&path) {
+                Ok(hash) if hash.eq_ignore_ascii_case(&file.hash) => {
+                    files.insert(file.path.clone(), std::fs::metadata(&path)?.len());
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(FileServer {
+            manifest,
+            base_path,
+            files,
+            base_url: None,
+            signing_key: None,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        })
+    }
+
+    /// Number of verified files that are served
+    pub fn served_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Total number of files in the manifest
+    pub fn file_count(&self) -> usize {
+        self.manifest.files.len()
+    }
+
+    /// Manifest whose verified files are downloaded from `base_url`
+    pub fn manifest(&self, base_url: &Url) -> Result<Manifest, Box<dyn Error>> {
+        let mut manifest = self.manifest.clone();
+        for file in &mut manifest.files {
+            if self.files.contains_key(&file.path) {
+                mirror::rewrite_file(file, base_url)?;
+            }
+        }
+        manifest.origin = Origin::default();
+        Ok(manifest)
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        let local_addr = listener.local_addr()?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // Errors only affect this connection, e.g. a client that disconnected
+                let _ = server.handle_connection(stream, local_addr).await;
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, local_addr: SocketAddr) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let request = tokio::time::timeout(self.read_timeout, read_request(&mut reader))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Request timed out"))??;
+            let Some(request) = request else {
+                break;
+            };
+            let close = request
+                .headers
+                .get("connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+            self.respond(reader.get_mut(), &request, local_addr).await?;
+            if close {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn respond(
+        &self,
+        stream: &mut TcpStream,
+        request: &Request,
+        local_addr: SocketAddr,
+    ) -> io::Result<()> {
+        if request.method != "GET" && request.method != "HEAD" {
+            return write_head(stream, "405 Method Not Allowed", &[], 0).await;
+        }
+        let head_only = request.method == "HEAD";
+        let path = request.target.split(['?', '#']).next().unwrap_or_default();
+        let path = percent_decode_str(path.trim_start_matches('/')).decode_utf8_lossy();
+
+        let signature_requested = path
+            .strip_suffix(SIGNATURE_EXTENSION)
+            .is_some_and(|path| path == MANIFEST_FILE);
+        if path == MANIFEST_FILE || (signature_requested && self.signing_key.is_some()) {
+            let base_url = match &self.base_url {
+                Some(base_url) => base_url.clone(),
+                None => {
+                    let host = request
+                        .headers
+                        .get("host")
+                        .cloned()
+                        .unwrap_or_else(|| local_addr.to_string());
+                    Url::parse(&format!("http://{host}/"))
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+                }
+            };
+            let mut body = self
+                .manifest(&base_url)
+                .and_then(|manifest| Ok(serde_json::to_vec_pretty(&manifest)?))
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            let mut content_type = "application/json";
+            // The signature covers the manifest as served for the same host
+            if let (true, Some(key)) = (signature_requested, &self.signing_key) {
+                body = signature::sign(key, &body).into_bytes();
+                content_type = "text/plain";
+            }
+            write_head(
+                stream,
+                "200 OK",
+                &[("Content-Type", content_type.to_string())],
+                body.len() as u64,
+            )
+            .await?;
+            if !head_only {
+                stream.write_all(&body).await?;
+            }
+            return Ok(());
+        }
+
+        let Some(&size) = self.files.get(path.as_ref()) else {
+            return write_head(stream, "404 Not Found", &[], 0).await;
+        };
+        let accept_ranges = ("Accept-Ranges", "bytes".to_string());
+        let (status, start, length, mut headers) =
+            match parse_range(request.headers.get("range"), size) {
+                Range::Full => ("200 OK", 0, size, vec![accept_ranges]),
+                Range::Partial { start, end } => (
+                    "206 Partial Content",
+                    start,
+                    end - start + 1,
+                    vec![
+                        accept_ranges,
+                        ("Content-Range", format!("bytes {start}-{end}/{size}")),
+                    ],
+                ),
+                Range::Unsatisfiable => {
+                    let headers = [("Content-Range", format!("bytes */{size}"))];
+                    return write_head(stream, "416 Range Not Satisfiable", &headers, 0).await;
+                }
+            };
+        headers.push(("Content-Type", "application/octet-stream".to_string()));
+
+        let mut file = tokio::fs::File::open(self.base_path.join(path.as_ref())).await?;
+        write_head(stream, status, &headers, length).await?;
+        if !head_only {
+            file.seek(io::SeekFrom::Start(start)).await?;
+            let copied = tokio::io::copy(&mut file.take(length), stream).await?;
+            if copied != length {
+                // The file shrank while serving it, the response cannot be completed
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the next request from a connection, `None` once the client closed it
+async fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut request_line = String::new();
+    if read_line(reader, &mut request_line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Malformed request line",
+        ));
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        headers: HashMap::new(),
+    };
+    loop {
+        let mut line = String::new();
+        if read_line(reader, &mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if request.headers.len() == MAX_HEADERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many headers",
+            ));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            request
+                .headers
+                .insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(Some(request))
+}
+
+/// Read one line of at most [`MAX_LINE_LENGTH`] bytes
+async fn read_line(reader: &mut BufReader<TcpStream>, line: &mut String) -> io::Result<usize> {
+    let read = (&mut *reader).take(MAX_LINE_LENGTH).read_line(line).await?;
+    if read as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+    }
+    Ok(read)
+}
+
+/// Parse a single `bytes=` range. Multiple ranges are answered with the full file.
+fn parse_range(header: Option<&String>, size: u64) -> Range {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return Range::Full;
+    };
+    if spec.contains(',') {
+        return Range::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Range::Full;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        // Suffix range, the last `end` bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) | Err(_) => return Range::Unsatisfiable,
+            Ok(suffix) => (size.saturating_sub(suffix), size.saturating_sub(1)),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, size.saturating_sub(1)),
+            Err(_) => return Range::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return Range::Full,
+        },
+    };
+    if start >= size {
+        return Range::Unsatisfiable;
+    }
+    Range::Partial { start, end }
+}
+
+async fn write_head(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    content_length: u64,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\nContent-Length: {content_length}\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await
+}
@@ -0,0 +1,164 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Base64 encoded Ed25519 public keys trusted by every build of the patcher
+pub const EMBEDDED_KEYS: &[&str] = &[];
+
+/// Extension appended to the manifest location to find its detached signature
+pub const SIGNATURE_EXTENSION: &str = ".sig";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Outcome of checking a manifest signature
+pub enum SignatureStatus {
+    /// Signed by one of the trusted keys
+    Verified,
+    /// No signature was found
+    #[default]
+    Unsigned,
+    /// A signature was found but does not match any trusted key
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+/// Public keys accepted for manifest signatures.
+///
+/// Signatures are detached Ed25519 signatures over the raw manifest bytes, stored base64
+/// encoded next to the manifest with a `.sig` extension. Keys are trusted through
+/// [`EMBEDDED_KEYS`], the keys file, a profile or the command line. Signatures are always
+/// enforced unless `allow_unsigned` is set, so without any trusted key every manifest is refused.
+pub struct TrustedKeys {
+    keys: Vec<VerifyingKey>,
+    /// Accept unsigned or badly signed manifests instead of refusing them
+    pub allow_unsigned: bool,
+}
+
+impl Default for TrustedKeys {
+    fn default() -> Self {
+        let keys = EMBEDDED_KEYS
+            .iter()
+            .map(|key| parse_key(key).expect("embedded keys are valid"))
+            .collect();
+        TrustedKeys {
+            keys,
+            allow_unsigned: false,
+        }
+    }
+}
+
+/// Parse a base64 encoded Ed25519 public key
+pub fn parse_key(key: &str) -> Result<VerifyingKey, &'static str> {
+    let bytes = BASE64
+        .decode(key.trim())
+        .map_err(|_| "Public key is not valid base64")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Public key must be 32 bytes")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| "Public key is not a valid Ed25519 key")
+}
+
+/// Load a base64 encoded Ed25519 secret key from a file, used to sign the manifests written by
+/// the mirror and serve commands
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read the signing key {}: {e}", path.display()))?;
+    let bytes = BASE64
+        .decode(contents.trim())
+        .map_err(|_| "Signing key is not valid base64")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Signing key must be 32 bytes")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Base64 encoded detached signature of `contents`
+pub fn sign(key: &SigningKey, contents: &[u8]) -> String {
+    BASE64.encode(key.sign(contents).to_bytes())
+}
+
+/// Base64 encoded public key of a signing key, as given to `--trusted-key`
+pub fn public_key(key: &SigningKey) -> String {
+    BASE64.encode(key.verifying_key().to_bytes())
+}
+
+impl TrustedKeys {
+    /// Default location of the keys file inside the patcher configuration directory
+    pub fn default_path() -> Option<PathBuf> {
+        super::config::config_dir().map(|dir| dir.join("trusted-keys.txt"))
+    }
+
+    /// Trust an additional base64 encoded public key
+    pub fn add_key(&mut self, key: &str) -> Result<(), &'static str> {
+        self.keys.push(parse_key(key)?);
+        Ok(())
+    }
+
+    /// Trust the keys listed in a file, one base64 encoded key per line. Empty lines and lines
+    /// starting with `#` are skipped. A missing file adds no keys.
+    pub fn add_keys_file(&mut self, path: &Path) -> Result<(), &'static str> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(_) => return Err("Failed to read the trusted keys file"),
+        };
+        for line in contents.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                self.add_key(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether signatures are enforced, i.e. unsigned manifests are not explicitly allowed
+    pub fn is_enforced(&self) -> bool {
+        !self.allow_unsigned
+    }
+
+    /// Check a detached signature for `contents`.
+    ///
+    /// Returns an error for unsigned or badly signed manifests when signatures are enforced,
+    /// see [`TrustedKeys::is_enforced`]. Otherwise the status is returned so it can be shown
+    /// to the user.
+    pub fn verify(
+        &self,
+        contents: &[u8],
+        signature: Option<&str>,
+    ) -> Result<SignatureStatus, String> {
+        let status = match signature {
+            None => SignatureStatus::Unsigned,
+            Some(signature) => {
+                let verified = BASE64
+                    .decode(signature.trim())
+                    .ok()
+                    .and_then(|bytes| Signature::from_slice(&bytes).ok())
+                    .is_some_and(|signature| {
+                        self.keys
+                            .iter()
+                            .any(|key| key.verify(contents, &signature).is_ok())
+                    });
+                if verified {
+                    SignatureStatus::Verified
+                } else {
+                    SignatureStatus::Invalid
+                }
+            }
+        };
+
+        match status {
+            SignatureStatus::Verified => Ok(status),
+            _ if !self.is_enforced() => Ok(status),
+            SignatureStatus::Unsigned => {
+                Err("Manifest is not signed. Use --allow-unsigned to accept it anyway".to_string())
+            }
+            SignatureStatus::Invalid if self.keys.is_empty() => Err("Manifest is signed but no key \
+                is trusted. Use --trusted-key to trust its key, or --allow-unsigned to accept it anyway"
+                .to_string()),
+            SignatureStatus::Invalid => Err("Manifest signature does not match any trusted key. \
+                Use --allow-unsigned to accept it anyway"
+                .to_string()),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::atomic_file;
+use super::channel::Channel;
+use super::manifest::Provider;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// Record of the manifest last applied to an installation.
+///
+/// # Fields
+///
+/// - `uid` - UID of the applied manifest.
+/// - `version` - Version of the applied manifest.
+/// - `source` - Manifest location the manifest was loaded from.
+/// - `provider` - Provider used for downloads.
+/// - `channel` - Release channel, if the manifest location was built from one.
+/// - `applied_at` - Unix timestamp of the transaction.
+/// - `files` - Map of file paths to the hashes that were installed.
+/// - `groups` - Optional content groups selected by the user.
+pub struct InstallState {
+    pub uid: String,
+    pub version: String,
+    pub source: Option<String>,
+    pub provider: Option<Provider>,
+    pub channel: Option<Channel>,
+    pub applied_at: u64,
+    pub files: BTreeMap<String, String>,
+    #[serde(default)]
+    pub groups: BTreeSet<String>,
+}
+
+impl InstallState {
+    /// State file of an installation
+    pub fn path(base_path: &Path) -> PathBuf {
+        super::config::data_dir(base_path).join("state.json")
+    }
+
+    /// Load the state of an installation, `None` if no manifest was applied yet
+    pub fn load(base_path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = Self::path(base_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&contents)?))
+    }
+
+    /// Record the state of an installation
+    pub fn save(&self, base_path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = serde_json::to_string_pretty(self)?;
+        atomic_file::write(&Self::path(base_path), contents.as_bytes())?;
+        Ok(())
+    }
+}
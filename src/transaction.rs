@@ -1,13 +1,26 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use colored::Colorize;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use humansize::BINARY;
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
-use super::manifest::{Manifest, PatchFile, Provider};
+use super::bundle::{Bundle, BundleEntry};
+use super::channel::Channel;
+use super::download_cache::DownloadCache;
+use super::encoding::Decompressor;
+use super::format::{age_to_human_readable, unix_timestamp};
+use super::ignore::IgnoreRules;
+use super::manifest::{
+    provider_url, Chunk, CompressedVariant, Delta, Manifest, Origin, PatchFile, Provider,
+};
+use super::policy::{ModifiedPolicy, PolicyRules};
+use super::signature::SignatureStatus;
+use super::state::InstallState;
+use super::transport::{ByteStream, Source, TransportError};
 use super::Progress;
 
 #[derive(PartialEq, Clone)]
@@ -15,6 +28,18 @@ enum Status {
     Present,
     OutOfDate,
     Missing,
+    /// Modified by the user and kept by policy
+    Kept,
+    /// Excluded by ignore rules or the custom content mode
+    Ignored,
+    /// Installed file that will be deleted
+    Remove,
+}
+
+impl Status {
+    fn is_pending(&self) -> bool {
+        matches!(self, Status::OutOfDate | Status::Missing)
+    }
 }
 
 #[derive(Clone)]
@@ -27,48 +52,204 @@ enum Status {
 /// - `patch_file`: The patch file associated with the operation.
 /// - `size`: The size of the patch, represented as a 64-bit signed integer.
 /// - `status`: The status of the file operation.
+/// - `modified`: The policy applied if the user changed the file since it was installed.
+/// - `delta`: A delta that turns the local file into the manifest version.
+/// - `stale_chunks`: Indices of the chunks that differ from the local file.
+/// - `local_hash`: Hash of the local file if it was computed and differs from the manifest.
+/// - `reuse`: Path of another local file with the contents this file needs.
+/// - `cached`: The download cache has the contents this file needs.
+/// - `bundled`: Where the offline bundle holds the contents this file needs.
 struct FileOperation {
     patch_file: PatchFile,
     size: i64,
     status: Status,
+    modified: Option<ModifiedPolicy>,
+    delta: Option<Delta>,
+    stale_chunks: Vec<usize>,
+    local_hash: Option<String>,
+    reuse: Option<String>,
+    cached: bool,
+    bundled: Option<BundleEntry>,
+}
+
+/// What is downloaded to install a file
+#[derive(Clone, Copy)]
+enum Payload<'a> {
+    /// A local file with the same contents, copied or hard linked
+    Local(&'a str),
+    /// The entry for the file's hash in the download cache
+    Cached,
+    /// The file inside the offline bundle
+    Bundled(BundleEntry),
+    /// A delta applied to the local file
+    Delta(&'a Delta),
+    /// The chunks with these indices, written over a copy of the local file
+    Chunks(&'a [usize]),
+    /// A compressed copy decompressed while streaming
+    Compressed(&'a CompressedVariant),
+    /// The raw file
+    Full,
+}
+
+impl Payload<'_> {
+    /// Number of bytes to download
+    fn size(&self, file: &PatchFile) -> i64 {
+        match self {
+            Payload::Local(_) | Payload::Cached => 0,
+            Payload::Bundled(entry) => entry.size as i64,
+            Payload::Delta(delta) => delta.size,
+            Payload::Chunks(indices) => indices
+                .iter()
+                .map(|&index| file.chunks[index].size as i64)
+                .sum(),
+            Payload::Compressed(compressed) => compressed.size,
+            Payload::Full => file.size,
+        }
+    }
+
+    /// Whether the payload is fetched from the file's sources rather than found locally
+    fn is_remote(&self) -> bool {
+        matches!(
+            self,
+            Payload::Delta(_) | Payload::Chunks(_) | Payload::Compressed(_) | Payload::Full
+        )
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Payload::Local(_) => "a local copy",
+            Payload::Cached => "the download cache",
+            Payload::Bundled(_) => "the bundle",
+            Payload::Delta(_) => "a delta",
+            Payload::Chunks(_) => "changed chunks",
+            Payload::Compressed(_) => "a compressed copy",
+            Payload::Full => "the raw file",
+        }
+    }
 }
 
 impl FileOperation {
+    /// Operation for a file with nothing planned beyond its status
+    fn new(status: Status, file: &PatchFile, size: i64) -> Self {
+        FileOperation {
+            patch_file: file.clone(),
+            size,
+            status,
+            modified: None,
+            delta: None,
+            stale_chunks: Vec::new(),
+            local_hash: None,
+            reuse: None,
+            cached: false,
+            bundled: None,
+        }
+    }
+
     /// Process the manifest and return a list of file operations
-    fn process(manifest: &Manifest, base_path: &std::path::Path) -> Vec<FileOperation> {
+    fn process(
+        manifest: &Manifest,
+        base_path: &std::path::Path,
+        options: &TransactionOptions,
+    ) -> Vec<FileOperation> {
+        // Files installed by the same manifest only need a size check in quick mode
+        let applied_files = options
+            .state
+            .as_ref()
+            .filter(|state| options.quick_verify && state.uid == manifest.uid)
+            .map(|state| &state.files);
+
         manifest
             .files
             .iter()
-            .map(|file| {
-                let full_path = base_path.join(&file.path);
-                if !full_path.exists() {
+            .map(|file| (file, base_path.join(&file.path)))
+            // Files of other locales are not planned. Custom files that are already gone need no
+            // operation when removing custom content, unselected optional files only need one if
+            // the patcher installed them earlier
+            .filter(|(file, full_path)| {
+                if !file.is_for_locale(options.locale.as_deref()) {
+                    return false;
+                }
+                if file.custom && options.custom == CustomMode::Remove {
+                    return full_path.exists();
+                }
+                if !options.is_selected(file) {
+                    return full_path.exists() && options.is_installed(&file.path);
+                }
+                true
+            })
+            .map(|(file, full_path)| {
+                if options.ignore.is_ignored(&file.path) || !options.custom.includes(file) {
+                    return FileOperation::new(
+                        Status::Ignored,
+                        file,
+                        std::fs::metadata(&full_path).map_or(0, |m| m.len() as i64),
+                    );
+                }
+                if (file.custom && options.custom == CustomMode::Remove)
+                    || !options.is_selected(file)
+                {
+                    let modified = Self::modified_before_removal(file, &full_path, options);
+                    let status = if modified == Some(ModifiedPolicy::Keep) {
+                        Status::Kept
+                    } else {
+                        Status::Remove
+                    };
                     return FileOperation {
-                        status: Status::Missing,
-                        patch_file: file.clone(),
-                        size: 0,
+                        modified,
+                        ..FileOperation::new(
+                            status,
+                            file,
+                            std::fs::metadata(&full_path).map_or(0, |m| m.len() as i64),
+                        )
                     };
                 }
+                if !full_path.exists() {
+                    return FileOperation::new(Status::Missing, file, 0);
+                }
+
+                let new_size: i64 = std::fs::metadata(&full_path)
+                    .unwrap_or_else(|_| {
+                        panic!("Failed to read metadata for file: {:?}", &full_path)
+                    })
+                    .len()
+                    .try_into()
+                    .unwrap();
+
+                if applied_files.is_some_and(|files| files.get(&file.path) == Some(&file.hash))
+                    && new_size == file.size
+                {
+                    return FileOperation::new(Status::Present, file, new_size);
+                }
 
                 match std::fs::read(&full_path) {
                     Ok(contents) => {
-                        let digest = md5::compute(contents);
+                        let digest = md5::compute(&contents);
                         let digest_str = format!("{digest:x}");
-                        let new_size: i64 = std::fs::metadata(&full_path)
-                            .unwrap_or_else(|_| {
-                                panic!("Failed to read metadata for file: {:?}", &full_path)
-                            })
-                            .len()
-                            .try_into()
-                            .unwrap();
+                        if digest_str == file.hash {
+                            return FileOperation::new(Status::Present, file, new_size);
+                        }
 
+                        // The file differs from what was installed, so the user changed it.
+                        // Without a record it is treated as an ordinary outdated file.
+                        let installed_hash = options
+                            .state
+                            .as_ref()
+                            .and_then(|state| state.files.get(&file.path));
+                        let modified = installed_hash
+                            .filter(|installed_hash| **installed_hash != digest_str)
+                            .map(|_| options.modified_policy.policy_for(&file.path));
+
+                        let status = if modified == Some(ModifiedPolicy::Keep) {
+                            Status::Kept
+                        } else {
+                            Status::OutOfDate
+                        };
                         FileOperation {
-                            status: if digest_str == file.hash {
-                                Status::Present
-                            } else {
-                                Status::OutOfDate
-                            },
-                            patch_file: file.clone(),
-                            size: new_size,
+                            modified,
+                            delta: file.delta_from(&digest_str).cloned(),
+                            stale_chunks: file.stale_chunks(&contents),
+                            local_hash: Some(digest_str),
+                            ..FileOperation::new(status, file, new_size)
                         }
                     }
                     Err(e) => {
@@ -78,23 +259,255 @@ impl FileOperation {
             })
             .collect()
     }
+
+    /// Policy for a file about to be removed that the user changed since it was installed.
+    /// Files without a record are removed as they are.
+    fn modified_before_removal(
+        file: &PatchFile,
+        full_path: &Path,
+        options: &TransactionOptions,
+    ) -> Option<ModifiedPolicy> {
+        let installed_hash = options.state.as_ref()?.files.get(&file.path)?;
+        let current_hash = super::manifest::hash_file(full_path).ok()?;
+        (current_hash != *installed_hash).then(|| options.modified_policy.policy_for(&file.path))
+    }
+
+    /// Point pending files at local files that already have their contents, e.g. duplicates or
+    /// files that moved to another directory. Recorded hashes of files left over from the previous
+    /// installation are checked again before they are used.
+    fn find_local_copies(
+        operations: &mut [FileOperation],
+        manifest: &Manifest,
+        base_path: &std::path::Path,
+        options: &TransactionOptions,
+    ) {
+        let mut sources: BTreeMap<String, String> = BTreeMap::new();
+        for op in operations.iter() {
+            let hash = match (&op.status, &op.local_hash) {
+                (Status::Present, _) => &op.patch_file.hash,
+                (Status::OutOfDate | Status::Kept, Some(hash)) => hash,
+                _ => continue,
+            };
+            sources
+                .entry(hash.clone())
+                .or_insert_with(|| op.patch_file.path.clone());
+        }
+        if let Some(state) = &options.state {
+            for (path, hash) in &state.files {
+                if manifest.files.iter().all(|file| &file.path != path)
+                    && !options.ignore.is_ignored(path)
+                    && base_path.join(path).is_file()
+                {
+                    sources.entry(hash.clone()).or_insert_with(|| path.clone());
+                }
+            }
+        }
+
+        for op in operations.iter_mut().filter(|op| op.status.is_pending()) {
+            op.reuse = sources
+                .get(&op.patch_file.hash)
+                .filter(|path| **path != op.patch_file.path)
+                .cloned();
+        }
+    }
+
+    /// Number of bytes to download using the preferred payload
+    fn download_size(&self) -> i64 {
+        self.payloads()[0].size(&self.patch_file)
+    }
+
+    /// Ways to obtain the file in order of preference
+    fn payloads(&self) -> Vec<Payload<'_>> {
+        let mut payloads = Vec::new();
+        if let Some(path) = &self.reuse {
+            payloads.push(Payload::Local(path));
+        }
+        if self.cached {
+            payloads.push(Payload::Cached);
+        }
+        if let Some(entry) = self.bundled {
+            payloads.push(Payload::Bundled(entry));
+        }
+        if let Some(delta) = &self.delta {
+            payloads.push(Payload::Delta(delta));
+        }
+        let whole = match &self.patch_file.compressed {
+            Some(compressed) => Payload::Compressed(compressed),
+            None => Payload::Full,
+        };
+        // Chunks are only worth it if they cost less than downloading the whole file
+        let chunks = Payload::Chunks(&self.stale_chunks);
+        if !self.stale_chunks.is_empty()
+            && chunks.size(&self.patch_file) < whole.size(&self.patch_file)
+        {
+            payloads.push(chunks);
+        }
+        if let Some(compressed) = &self.patch_file.compressed {
+            payloads.push(Payload::Compressed(compressed));
+        }
+        payloads.push(Payload::Full);
+        payloads
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How to treat files marked `Custom` in the manifest, i.e. server content that is not part of
+/// the stock client
+pub enum CustomMode {
+    /// Patch custom and stock files
+    #[default]
+    Install,
+    /// Leave custom files untouched
+    Skip,
+    /// Leave stock files untouched
+    Only,
+    /// Delete installed custom files and patch stock files
+    Remove,
+}
+
+impl CustomMode {
+    /// Check whether a file is patched in this mode
+    fn includes(&self, file: &PatchFile) -> bool {
+        match self {
+            CustomMode::Install | CustomMode::Remove => true,
+            CustomMode::Skip => !file.custom,
+            CustomMode::Only => file.custom,
+        }
+    }
+}
+
+/// Most connections used to download one file
+pub const MAX_SEGMENTS: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+/// Options controlling how a [`Transaction`] plans its operations
+pub struct TransactionOptions {
+    /// State recorded by the previous successful run against the installation
+    pub state: Option<InstallState>,
+    /// For a manifest that was already applied, trust the recorded hashes and only compare
+    /// file sizes instead of hashing every file
+    pub quick_verify: bool,
+    /// How to handle files the user changed since they were installed
+    pub modified_policy: PolicyRules,
+    /// Files the patcher must not touch
+    pub ignore: IgnoreRules,
+    /// How to treat custom server content
+    pub custom: CustomMode,
+    /// Selected optional content groups
+    pub groups: BTreeSet<String>,
+    /// Client locale, files tagged with another locale are left out
+    pub locale: Option<String>,
+    /// Hard link local files with matching contents instead of copying them
+    pub hard_link: bool,
+    /// Download cache shared with other installations
+    pub cache: Option<DownloadCache>,
+    /// Offline bundle used as the download source
+    pub bundle: Option<Bundle>,
+    /// Connections used to download a file of at least `segment_threshold` bytes from a server
+    /// that supports range requests, at most [`MAX_SEGMENTS`]. 0 or 1 downloads every file over
+    /// a single connection.
+    pub segments: usize,
+    /// Size in bytes from which files are downloaded in segments
+    pub segment_threshold: u64,
+}
+
+impl TransactionOptions {
+    /// Check whether a file is wanted given the selected optional groups
+    fn is_selected(&self, file: &PatchFile) -> bool {
+        file.optional_group()
+            .is_none_or(|group| self.groups.contains(group))
+    }
+
+    /// Check whether the previous run installed a file
+    fn is_installed(&self, path: &str) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.files.contains_key(path))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileReport {
     pub path: String,
+    /// Custom server content rather than a stock client file
+    pub custom: bool,
     pub current_size: Option<i64>, // None if file is missing
     pub new_size: i64,
+    /// Size of the delta that will be applied instead of downloading the whole file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_size: Option<i64>,
+    /// Changed chunks that will be downloaded instead of the whole file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<ChunkReport>,
+    /// Local file with the same contents that will be copied instead of downloading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reused_from: Option<String>,
+    /// The file will be taken from the download cache
+    #[serde(default)]
+    pub cached: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkReport {
+    pub changed: usize,
+    pub total: usize,
+    /// Combined size of the changed chunks
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModifiedFileReport {
+    pub path: String,
+    pub policy: ModifiedPolicy,
+    /// The file is removed rather than replaced, e.g. custom content being uninstalled
+    #[serde(default)]
+    pub removed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupReport {
+    pub name: String,
+    pub selected: bool,
+    pub files: usize,
+    pub size: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TransactionReport {
     pub version: String,
     pub uid: String,
+    pub channel: Option<Channel>,
+    pub source: Option<String>,
+    /// Unix timestamp of the cached manifest if it was used because the network was unavailable
+    pub stale_since: Option<u64>,
+    pub signature: SignatureStatus,
+    /// Manifest sources that were tried before `source` and why they were not used
+    pub skipped_sources: Vec<String>,
+    /// State recorded by the previous successful run, if any
+    pub installed: Option<InstallState>,
+    /// Files recorded for the same manifest were checked by size only
+    pub quick_verified: bool,
     pub up_to_date_files: Vec<FileReport>,
     pub outdated_files: Vec<FileReport>,
     pub missing_files: Vec<FileReport>,
+    /// Client locale the files were planned for
+    pub locale: Option<String>,
+    /// Optional content groups offered by the manifest
+    pub optional_groups: Vec<GroupReport>,
+    /// Files changed by the user since they were installed
+    pub modified_files: Vec<ModifiedFileReport>,
+    /// Files excluded by ignore rules, `current_size` is `None` if the file is missing
+    pub ignored_files: Vec<FileReport>,
+    /// Installed files that will be deleted
+    pub removed_files: Vec<FileReport>,
+    /// Size of the pending files once installed
+    pub total_inbound_size: u64,
+    /// Bytes to transfer, smaller than `total_inbound_size` when deltas or compressed copies are used
     pub total_download_size: u64,
+    /// Size of the pending files taken from local copies instead of being downloaded
+    pub total_reused_size: u64,
+    /// Size of the pending files taken from the download cache
+    pub total_cached_size: u64,
     pub disk_space_change: i64,
     pub base_path: PathBuf,
 }
@@ -104,17 +517,112 @@ pub struct Transaction {
     operations: Vec<FileOperation>,
     manifest_version: String,
     manifest_uid: String,
+    manifest_origin: Origin,
+    optional_groups: Vec<GroupReport>,
     pub base_path: PathBuf,
+    /// Release channel the manifest was fetched from, if known
+    pub channel: Option<Channel>,
+    options: TransactionOptions,
 }
 
 impl Transaction {
+    /// Create a transaction using the ignore file of the game directory
     pub fn new(manifest: Manifest, base_path: PathBuf) -> Self {
-        let operations = FileOperation::process(&manifest, &base_path);
+        let options = TransactionOptions {
+            ignore: IgnoreRules::load(&base_path).unwrap_or_default(),
+            ..Default::default()
+        };
+        Self::with_options(manifest, base_path, options)
+    }
+
+    pub fn with_options(
+        manifest: Manifest,
+        base_path: PathBuf,
+        options: TransactionOptions,
+    ) -> Self {
+        let mut operations = FileOperation::process(&manifest, &base_path, &options);
+        FileOperation::find_local_copies(&mut operations, &manifest, &base_path, &options);
+        for op in operations.iter_mut().filter(|op| op.status.is_pending()) {
+            if let Some(cache) = &options.cache {
+                op.cached = cache.contains(&op.patch_file.hash);
+            }
+            if let Some(bundle) = &options.bundle {
+                op.bundled = bundle.file(&op.patch_file.hash);
+            }
+        }
+
+        let mut groups: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+        for file in &manifest.files {
+            if let Some(group) = file.optional_group() {
+                let (files, size) = groups.entry(group).or_default();
+                *files += 1;
+                *size += file.size as u64;
+            }
+        }
+        let optional_groups = groups
+            .into_iter()
+            .map(|(name, (files, size))| GroupReport {
+                name: name.to_string(),
+                selected: options.groups.contains(name),
+                files,
+                size,
+            })
+            .collect();
+
         Transaction {
             operations,
+            optional_groups,
             manifest_version: manifest.version,
             manifest_uid: manifest.uid,
+            manifest_origin: manifest.origin,
             base_path,
+            channel: None,
+            options,
+        }
+    }
+
+    /// Whether the previous run applied the same manifest
+    pub fn is_applied(&self) -> bool {
+        self.options
+            .state
+            .as_ref()
+            .is_some_and(|state| state.uid == self.manifest_uid)
+    }
+
+    /// Build the state to record after this transaction completed successfully
+    pub fn install_state(&self, provider: &Provider) -> InstallState {
+        InstallState {
+            uid: self.manifest_uid.clone(),
+            version: self.manifest_version.clone(),
+            source: self
+                .manifest_origin
+                .location
+                .as_ref()
+                .map(|location| location.to_string()),
+            provider: Some(provider.clone()),
+            channel: self.channel.clone(),
+            applied_at: unix_timestamp(),
+            files: self
+                .operations
+                .iter()
+                .filter_map(|op| {
+                    let installed_hash = self
+                        .options
+                        .state
+                        .as_ref()
+                        .and_then(|state| state.files.get(&op.patch_file.path));
+                    // Kept files keep their installed hash so they are still detected as modified,
+                    // ignored files were not touched and keep whatever was recorded before
+                    let hash = match op.status {
+                        Status::Kept => installed_hash.unwrap_or(&op.patch_file.hash),
+                        Status::Ignored => installed_hash?,
+                        Status::Remove => return None,
+                        _ => &op.patch_file.hash,
+                    };
+                    Some((op.patch_file.path.clone(), hash.clone()))
+                })
+                .collect(),
+            groups: self.options.groups.clone(),
         }
     }
 
@@ -122,13 +630,29 @@ impl Transaction {
         TransactionReport {
             version: self.manifest_version.clone(),
             uid: self.manifest_uid.clone(),
+            channel: self.channel.clone(),
+            source: self
+                .manifest_origin
+                .location
+                .as_ref()
+                .map(|location| location.to_string()),
+            stale_since: self.manifest_origin.stale_since,
+            signature: self.manifest_origin.signature,
+            skipped_sources: self.manifest_origin.skipped.clone(),
+            installed: self.options.state.clone(),
+            quick_verified: self.options.quick_verify && self.is_applied(),
             up_to_date_files: self
                 .up_to_date()
                 .iter()
                 .map(|op| FileReport {
                     path: op.patch_file.path.clone(),
+                    custom: op.patch_file.custom,
                     current_size: Some(op.size),
                     new_size: op.patch_file.size,
+                    delta_size: None,
+                    chunks: None,
+                    reused_from: None,
+                    cached: false,
                 })
                 .collect(),
             outdated_files: self
@@ -136,8 +660,20 @@ impl Transaction {
                 .iter()
                 .map(|op| FileReport {
                     path: op.patch_file.path.clone(),
+                    custom: op.patch_file.custom,
                     current_size: Some(op.size),
                     new_size: op.patch_file.size,
+                    delta_size: op.delta.as_ref().map(|delta| delta.size),
+                    reused_from: op.reuse.clone(),
+                    cached: op.cached,
+                    chunks: op.payloads().into_iter().find_map(|payload| match payload {
+                        Payload::Chunks(indices) => Some(ChunkReport {
+                            changed: indices.len(),
+                            total: op.patch_file.chunks.len(),
+                            size: payload.size(&op.patch_file),
+                        }),
+                        _ => None,
+                    }),
                 })
                 .collect(),
             missing_files: self
@@ -145,11 +681,80 @@ impl Transaction {
                 .iter()
                 .map(|op| FileReport {
                     path: op.patch_file.path.clone(),
+                    custom: op.patch_file.custom,
                     current_size: None,
                     new_size: op.patch_file.size,
+                    delta_size: None,
+                    chunks: None,
+                    reused_from: op.reuse.clone(),
+                    cached: op.cached,
+                })
+                .collect(),
+            locale: self.options.locale.clone(),
+            optional_groups: self.optional_groups.clone(),
+            modified_files: self
+                .operations
+                .iter()
+                .filter_map(|op| {
+                    op.modified.map(|policy| ModifiedFileReport {
+                        path: op.patch_file.path.clone(),
+                        policy,
+                        removed: op.status == Status::Remove,
+                    })
+                })
+                .collect(),
+            ignored_files: self
+                .operations
+                .iter()
+                .filter(|op| op.status == Status::Ignored)
+                .map(|op| FileReport {
+                    path: op.patch_file.path.clone(),
+                    custom: op.patch_file.custom,
+                    current_size: self
+                        .base_path
+                        .join(&op.patch_file.path)
+                        .exists()
+                        .then_some(op.size),
+                    new_size: op.patch_file.size,
+                    delta_size: None,
+                    chunks: None,
+                    reused_from: None,
+                    cached: false,
                 })
                 .collect(),
+            removed_files: self
+                .operations
+                .iter()
+                .filter(|op| op.status == Status::Remove)
+                .map(|op| FileReport {
+                    path: op.patch_file.path.clone(),
+                    custom: op.patch_file.custom,
+                    current_size: Some(op.size),
+                    new_size: 0,
+                    delta_size: None,
+                    chunks: None,
+                    reused_from: None,
+                    cached: false,
+                })
+                .collect(),
+            total_inbound_size: self
+                .pending()
+                .iter()
+                .map(|op| op.patch_file.size as u64)
+                .sum(),
             total_download_size: self.total_download_size() as u64,
+            total_reused_size: self
+                .pending()
+                .iter()
+                .filter(|op| op.reuse.is_some())
+                .map(|op| op.patch_file.size as u64)
+                .sum(),
+            total_cached_size: self
+                .pending()
+                .iter()
+                .filter(|op| op.reuse.is_none() && op.cached)
+                .map(|op| op.patch_file.size as u64)
+                .sum(),
             disk_space_change: self.disk_space_change(),
             base_path: self.base_path.clone(),
         }
@@ -160,44 +765,205 @@ impl Transaction {
         println!("\nManifest Overview:");
         println!(" Version: {}", report.version);
         println!(" UID: {}", report.uid);
-        println!(" Base path: {}", report.base_path.display());
-
-        println!("\n {}", "Up-to-date files:".green());
-        for file in &report.up_to_date_files {
+        match &report.channel {
+            Some(Channel::Production) => println!(" Channel: {}", Channel::Production),
+            Some(channel) => println!(" Channel: {}", channel.key().yellow()),
+            None => {}
+        }
+        if let Some(source) = &report.source {
+            println!(" Source: {source}");
+        }
+        for skipped in &report.skipped_sources {
+            println!("  {}", format!("Skipped source {skipped}").yellow());
+        }
+        match report.signature {
+            SignatureStatus::Verified => println!(" Signature: {}", "verified".green()),
+            SignatureStatus::Unsigned => println!(" Signature: {}", "unsigned".red()),
+            SignatureStatus::Invalid => println!(" Signature: {}", "invalid".red()),
+        }
+        if let Some(fetched_at) = report.stale_since {
             println!(
-                "  {} (Size: {})",
-                file.path.green(),
-                humansize::format_size(file.new_size as u64, BINARY)
+                " {}",
+                format!(
+                    "Warning: the manifest could not be fetched, using the cached copy from {}. It may be stale.",
+                    age_to_human_readable(unix_timestamp().saturating_sub(fetched_at))
+                )
+                .yellow()
             );
         }
-
-        println!("\n {}", "Outdated files (will be updated):".yellow());
-        for file in &report.outdated_files {
+        println!(" Base path: {}", report.base_path.display());
+        if let Some(locale) = &report.locale {
+            println!(" Locale: {locale}");
+        }
+        if let Some(installed) = &report.installed {
             println!(
-                "  {} (Current Size: {}, New Size: {})",
-                file.path.yellow(),
-                humansize::format_size(file.current_size.unwrap() as u64, BINARY),
-                humansize::format_size(file.new_size as u64, BINARY)
+                " Installed: version {} ({}), applied {}",
+                installed.version,
+                installed.uid,
+                age_to_human_readable(unix_timestamp().saturating_sub(installed.applied_at))
             );
+            if installed.channel.is_some() && installed.channel != report.channel {
+                let channel_name = |channel: &Option<Channel>| {
+                    channel
+                        .as_ref()
+                        .map_or("none".to_string(), |channel| channel.to_string())
+                };
+                println!(
+                    " {}",
+                    format!(
+                        "Switching channel from {} to {}",
+                        channel_name(&installed.channel),
+                        channel_name(&report.channel)
+                    )
+                    .yellow()
+                );
+            }
+        }
+        if report.quick_verified {
+            println!(" Manifest already applied, files were verified by size only (use --verify to check hashes)");
+        }
+        if !report.optional_groups.is_empty() {
+            println!(" Optional groups (select with --group, deselect with --without-group):");
+            for group in &report.optional_groups {
+                let selection = if group.selected {
+                    "selected".green()
+                } else {
+                    "not selected".normal()
+                };
+                println!(
+                    "  {} ({}, {} files, {})",
+                    group.name,
+                    selection,
+                    group.files,
+                    humansize::format_size(group.size, BINARY)
+                );
+            }
         }
 
-        println!("\n {}", "Missing files (will be downloaded):".red());
-        for file in &report.missing_files {
-            println!(
-                "  {} (New Size: {})",
-                file.path.red(),
-                humansize::format_size(file.new_size as u64, BINARY)
-            );
+        // Custom server content is listed apart from stock client files when both are present
+        let listed: Vec<bool> = [false, true]
+            .into_iter()
+            .filter(|custom| {
+                self.operations.iter().any(|op| {
+                    op.patch_file.custom == *custom
+                        && !matches!(op.status, Status::Ignored | Status::Remove)
+                })
+            })
+            .collect();
+        for &custom in listed.iter() {
+            if listed.len() > 1 {
+                let group = if custom {
+                    "Custom content"
+                } else {
+                    "Client files"
+                };
+                println!("\n{}", group.bold());
+            }
+            let in_group = |file: &&FileReport| file.custom == custom;
+
+            println!("\n {}", "Up-to-date files:".green());
+            for file in report.up_to_date_files.iter().filter(in_group) {
+                println!(
+                    "  {} (Size: {})",
+                    file.path.green(),
+                    humansize::format_size(file.new_size as u64, BINARY)
+                );
+            }
+
+            println!("\n {}", "Outdated files (will be updated):".yellow());
+            for file in report.outdated_files.iter().filter(in_group) {
+                let delta = match (&file.reused_from, file.delta_size, &file.chunks) {
+                    (Some(path), _, _) => format!(", Copy of: {path}"),
+                    _ if file.cached => ", Cached".to_string(),
+                    (None, Some(size), _) => {
+                        format!(", Delta: {}", humansize::format_size(size as u64, BINARY))
+                    }
+                    (None, None, Some(chunks)) => format!(
+                        ", Changed chunks: {}/{} ({})",
+                        chunks.changed,
+                        chunks.total,
+                        humansize::format_size(chunks.size as u64, BINARY)
+                    ),
+                    (None, None, None) => String::new(),
+                };
+                println!(
+                    "  {} (Current Size: {}, New Size: {}{})",
+                    file.path.yellow(),
+                    humansize::format_size(file.current_size.unwrap() as u64, BINARY),
+                    humansize::format_size(file.new_size as u64, BINARY),
+                    delta
+                );
+            }
+
+            println!("\n {}", "Missing files (will be downloaded):".red());
+            for file in report.missing_files.iter().filter(in_group) {
+                let reused = match &file.reused_from {
+                    Some(path) => format!(", Copy of: {path}"),
+                    None if file.cached => ", Cached".to_string(),
+                    None => String::new(),
+                };
+                println!(
+                    "  {} (New Size: {}{})",
+                    file.path.red(),
+                    humansize::format_size(file.new_size as u64, BINARY),
+                    reused
+                );
+            }
+        }
+
+        if !report.removed_files.is_empty() {
+            println!("\n {}", "Files to remove:".red());
+            for file in &report.removed_files {
+                println!(
+                    "  {} (Size: {})",
+                    file.path.red(),
+                    humansize::format_size(file.current_size.unwrap_or(0) as u64, BINARY)
+                );
+            }
+        }
+
+        if !report.modified_files.is_empty() {
+            println!("\n {}", "Files modified since installation:".cyan());
+            for file in &report.modified_files {
+                let description = if file.removed {
+                    file.policy.removal_description()
+                } else {
+                    file.policy.description()
+                };
+                println!("  {} ({})", file.path.cyan(), description);
+            }
+        }
+
+        if !report.ignored_files.is_empty() {
+            println!("\n {}", "Ignored files (will not be touched):".dimmed());
+            for file in &report.ignored_files {
+                println!("  {}", file.path.dimmed());
+            }
         }
 
         if self.has_pending_operations() {
             println!("\nTransaction Summary:");
             println!(" Installing/Updating: {} files", self.pending_count());
+            if self.removal_count() > 0 {
+                println!(" Removing: {} files", self.removal_count());
+            }
             println!(
                 "\nTotal size of inbound files is {}. Need to download {}.",
-                humansize::format_size(report.total_download_size, BINARY),
+                humansize::format_size(report.total_inbound_size, BINARY),
                 humansize::format_size(report.total_download_size, BINARY)
             );
+            if report.total_reused_size > 0 {
+                println!(
+                    "Reusing local copies saves downloading {}.",
+                    humansize::format_size(report.total_reused_size, BINARY)
+                );
+            }
+            if report.total_cached_size > 0 {
+                println!(
+                    "The download cache saves downloading {}.",
+                    humansize::format_size(report.total_cached_size, BINARY)
+                );
+            }
 
             let disk_space_change = report.disk_space_change;
             if disk_space_change > 0 {
@@ -231,10 +997,50 @@ impl Transaction {
     fn pending(&self) -> Vec<&FileOperation> {
         self.operations
             .iter()
-            .filter(|op| op.status != Status::Present)
+            .filter(|op| op.status.is_pending())
             .collect()
     }
 
+    /// Stream the chunks with the given indices one after another. Chunks with their own URLs are
+    /// downloaded from them, the others are requested from the file's source by range.
+    fn open_chunks(
+        &self,
+        http_client: &reqwest::Client,
+        provider: &Provider,
+        file: &PatchFile,
+        file_source: &Source,
+        indices: &[usize],
+    ) -> Result<ByteStream, TransportError> {
+        let mut requests = Vec::new();
+        for &index in indices {
+            let chunk = &file.chunks[index];
+            let request = match provider_url(&chunk.urls, provider) {
+                Some(url) => (
+                    Source::resolve(url, self.manifest_origin.location.as_ref())
+                        .map_err(|e| TransportError::Failed(e.into()))?,
+                    None,
+                ),
+                None => (file_source.clone(), Some((chunk.offset, chunk.size))),
+            };
+            requests.push(request);
+        }
+
+        let client = http_client.clone();
+        Ok(futures::stream::iter(requests)
+            .then(move |(source, range)| {
+                let client = client.clone();
+                async move {
+                    match range {
+                        Some((offset, size)) => source.open_range(&client, offset, size).await,
+                        None => source.open(&client).await,
+                    }
+                    .map_err(std::io::Error::other)
+                }
+            })
+            .try_flatten()
+            .boxed())
+    }
+
     fn missing(&self) -> Vec<&FileOperation> {
         self.operations
             .iter()
@@ -245,20 +1051,28 @@ impl Transaction {
     pub fn pending_count(&self) -> usize {
         self.operations
             .iter()
-            .filter(|x| x.status != Status::Present)
+            .filter(|x| x.status.is_pending())
+            .count()
+    }
+
+    /// Number of installed files that will be deleted
+    pub fn removal_count(&self) -> usize {
+        self.operations
+            .iter()
+            .filter(|op| op.status == Status::Remove)
             .count()
     }
 
     pub fn has_pending_operations(&self) -> bool {
-        self.pending_count() > 0
+        self.pending_count() > 0 || self.removal_count() > 0
     }
 
     fn total_download_size(&self) -> i64 {
         let total = self
             .operations
             .iter()
-            .filter(|x| x.status != Status::Present)
-            .map(|x| x.patch_file.size)
+            .filter(|x| x.status.is_pending())
+            .map(|x| x.download_size())
             .sum();
         assert!(
             total >= 0,
@@ -270,90 +1084,975 @@ impl Transaction {
     fn disk_space_change(&self) -> i64 {
         self.operations
             .iter()
-            .filter(|x| x.status != Status::Present)
-            .map(|x| x.patch_file.size - x.size)
+            .map(|x| match x.status {
+                Status::Remove => -x.size,
+                ref status if status.is_pending() => x.patch_file.size - x.size,
+                _ => 0,
+            })
             .sum()
     }
 
+    /// Paths of modified files whose policy asks the user before overwriting
+    pub fn modified_to_confirm(&self) -> Vec<String> {
+        self.operations
+            .iter()
+            .filter(|op| op.modified == Some(ModifiedPolicy::Ask))
+            .map(|op| op.patch_file.path.clone())
+            .collect()
+    }
+
+    /// Record the user's answer for a modified file with the `ask` policy
+    pub fn confirm_modified(&mut self, path: &str, overwrite: bool) {
+        if let Some(op) = self
+            .operations
+            .iter_mut()
+            .find(|op| op.patch_file.path == path && op.modified == Some(ModifiedPolicy::Ask))
+        {
+            if overwrite {
+                op.modified = Some(ModifiedPolicy::Overwrite);
+            } else {
+                op.modified = Some(ModifiedPolicy::Keep);
+                op.status = Status::Kept;
+            }
+        }
+    }
+
+    /// Copy or hard link a local file with the contents a pending file needs. Returns false if
+    /// the source no longer has those contents.
+    async fn reuse_local_file(
+        &self,
+        source: &str,
+        op: &FileOperation,
+    ) -> Result<bool, Box<dyn Error>> {
+        let source_path = self.base_path.join(source);
+        let dest_path = self.base_path.join(&op.patch_file.path);
+        match hash_file(&source_path).await {
+            Ok(hash) if hash == op.patch_file.hash => {}
+            Ok(_) => {
+                eprintln!(
+                    "\n{source} changed, downloading {} instead",
+                    op.patch_file.path
+                );
+                return Ok(false);
+            }
+            Err(e) => {
+                eprintln!(
+                    "\nFailed to read {source}, downloading {} instead: {e}",
+                    op.patch_file.path
+                );
+                return Ok(false);
+            }
+        }
+
+        match tokio::fs::remove_file(&dest_path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        if self.options.hard_link {
+            match tokio::fs::hard_link(&source_path, &dest_path).await {
+                Ok(()) => {
+                    println!("\rLinked {} to {source}", op.patch_file.path);
+                    return Ok(true);
+                }
+                Err(e) => eprintln!(
+                    "\nFailed to hard link {} to {source}, copying it instead: {e}",
+                    op.patch_file.path
+                ),
+            }
+        }
+        tokio::fs::copy(&source_path, &dest_path).await?;
+        println!("\rCopied {} from {source}", op.patch_file.path);
+        Ok(true)
+    }
+
+    /// Copy the cached contents of a pending file into place. Returns false if the entry is
+    /// missing or corrupt, corrupt entries are removed from the cache.
+    async fn materialize_cached(&self, op: &FileOperation) -> Result<bool, Box<dyn Error>> {
+        let Some(cache) = &self.options.cache else {
+            return Ok(false);
+        };
+        let dest_path = self.base_path.join(&op.patch_file.path);
+        let target_path = partial_path(&dest_path);
+        let materialized = {
+            let (cache, hash, target_path) = (
+                cache.clone(),
+                op.patch_file.hash.clone(),
+                target_path.clone(),
+            );
+            tokio::task::spawn_blocking(move || cache.materialize(&hash, &target_path)).await?
+        };
+        if let Err(e) = materialized {
+            eprintln!(
+                "\nFailed to copy {} from the download cache: {e}",
+                op.patch_file.path
+            );
+            let _ = tokio::fs::remove_file(&target_path).await;
+            return Ok(false);
+        }
+        if hash_file(&target_path).await? != op.patch_file.hash {
+            eprintln!(
+                "\nCached copy of {} is corrupt, downloading it instead",
+                op.patch_file.path
+            );
+            let _ = tokio::fs::remove_file(&target_path).await;
+            let _ = cache.remove(&op.patch_file.hash);
+            return Ok(false);
+        }
+        tokio::fs::rename(&target_path, &dest_path).await?;
+        println!("\rCopied {} from the download cache", op.patch_file.path);
+        Ok(true)
+    }
+
+    /// Partial file a whole file is downloaded to: a new download cache entry if a cache is
+    /// configured, otherwise a file next to the installed one
+    async fn download_path(&self, op: &FileOperation) -> std::io::Result<PathBuf> {
+        match self.options.cache.clone() {
+            Some(cache) => {
+                let hash = op.patch_file.hash.clone();
+                tokio::task::spawn_blocking(move || cache.partial_path(&hash)).await?
+            }
+            None => Ok(partial_path(&self.base_path.join(&op.patch_file.path))),
+        }
+    }
+
+    /// Move a verified download from [`Transaction::download_path`] into place. With a download
+    /// cache, the download becomes a cache entry and is then copied into the installation.
+    async fn install_download(
+        &self,
+        op: &FileOperation,
+        download_path: &Path,
+    ) -> std::io::Result<()> {
+        let dest_path = self.base_path.join(&op.patch_file.path);
+        let Some(cache) = self.options.cache.clone() else {
+            return tokio::fs::rename(download_path, &dest_path).await;
+        };
+        let (hash, download_path) = (op.patch_file.hash.clone(), download_path.to_path_buf());
+        let target_path = partial_path(&dest_path);
+        {
+            let target_path = target_path.clone();
+            tokio::task::spawn_blocking(move || {
+                cache.commit(&hash, &download_path)?;
+                cache.materialize(&hash, &target_path)
+            })
+            .await??;
+        }
+        tokio::fs::rename(&target_path, &dest_path).await
+    }
+
+    /// Store an installed file in the download cache. Failures only print a warning since the
+    /// file itself was installed.
+    async fn add_to_cache(&self, op: &FileOperation) {
+        let Some(cache) = self.options.cache.clone() else {
+            return;
+        };
+        let hash = op.patch_file.hash.clone();
+        let path = self.base_path.join(&op.patch_file.path);
+        let inserted = tokio::task::spawn_blocking(move || cache.insert(&hash, &path))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        if let Err(e) = inserted {
+            eprintln!(
+                "\nFailed to add {} to the download cache: {e}",
+                op.patch_file.path
+            );
+        }
+    }
+
+    /// Copy the user's version of a modified file to the backup directory
+    async fn back_up(op: &FileOperation, path: &Path, backup_dir: &Path) -> std::io::Result<()> {
+        let backup_path = backup_dir.join(&op.patch_file.path);
+        if let Some(dir) = backup_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::copy(path, &backup_path).await?;
+        println!(
+            "\rBacked up {} to {}",
+            op.patch_file.path,
+            backup_path.display()
+        );
+        Ok(())
+    }
+
+    /// Delete files planned for removal, then download pending files
     pub async fn download<F>(
         &self,
+        http_client: &reqwest::Client,
         progress_handler: F,
         provider: Provider,
     ) -> Result<(), Box<dyn Error>>
     where
         F: Fn(&Progress) -> Result<(), Box<dyn Error>> + Send + 'static,
     {
-        let http_client = reqwest::Client::new();
+        if !self.modified_to_confirm().is_empty() {
+            return Err("Modified files must be confirmed before downloading".into());
+        }
+
+        let backup_dir = super::config::data_dir(&self.base_path)
+            .join("backups")
+            .join(unix_timestamp().to_string());
+        for op in self
+            .operations
+            .iter()
+            .filter(|op| op.status == Status::Remove)
+        {
+            let path = self.base_path.join(&op.patch_file.path);
+            if op.modified == Some(ModifiedPolicy::Backup) && path.exists() {
+                Self::back_up(op, &path, &backup_dir).await?;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => println!("Removed {}", op.patch_file.path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(format!("Failed to remove {}: {e}", op.patch_file.path).into())
+                }
+            }
+        }
+
         let mut total_size_downloaded = 0;
+        let mut failed = Vec::new();
         let total_download_size = self.total_download_size();
-        for (idx, op) in self.pending().iter().enumerate() {
+        // Local copies are made first, before their sources are updated or removed
+        let mut pending = self.pending();
+        pending.sort_by_key(|op| op.reuse.is_none());
+        for (idx, op) in pending.iter().enumerate() {
             // Create parent directories if they don't exist
             let dest_path = self.base_path.join(&op.patch_file.path);
             if let Some(dir) = dest_path.parent() {
                 tokio::fs::create_dir_all(dir).await?;
             }
 
-            // Get URL for the specified provider
-            let url = op.patch_file.get_url(&provider).ok_or_else(|| {
-                format!(
-                    "No URL found for provider {:?} for file {}",
-                    provider, op.patch_file.path
-                )
-            })?;
-
-            let response = http_client.get(url).send().await?;
-            if !response.status().is_success() {
-                eprintln!("Failed to download {}: {}", url, response.status());
-                continue;
+            if op.modified == Some(ModifiedPolicy::Backup) {
+                Self::back_up(op, &dest_path, &backup_dir).await?;
             }
 
-            let file_size = op.patch_file.size;
-            let mut file = tokio::fs::File::create(dest_path.clone()).await?;
-            let start = std::time::Instant::now();
-            let mut downloaded: u64 = 0;
-
-            let mut stream = response.bytes_stream();
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk.map_err(|e| e.to_string())?;
-                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
-                downloaded += chunk.len() as u64;
-                total_size_downloaded += chunk.len() as u64;
+            // A local copy or matching delta is tried first, the raw file is the last resort
+            let mut installed = false;
+            for payload in op.payloads() {
+                // An offline import never falls back to the network
+                if self.options.bundle.is_some() && payload.is_remote() {
+                    continue;
+                }
+                let source = match payload {
+                    Payload::Local(path) => {
+                        if self.reuse_local_file(path, op).await? {
+                            installed = true;
+                            break;
+                        }
+                        continue;
+                    }
+                    Payload::Cached => {
+                        if self.materialize_cached(op).await? {
+                            installed = true;
+                            break;
+                        }
+                        continue;
+                    }
+                    Payload::Bundled(_) => match &self.options.bundle {
+                        Some(bundle) => Source::File(bundle.path.clone()),
+                        None => continue,
+                    },
+                    _ => {
+                        // Get URL for the specified provider
+                        let url = match payload {
+                            Payload::Delta(delta) => provider_url(&delta.urls, &provider),
+                            Payload::Compressed(compressed) => {
+                                provider_url(&compressed.urls, &provider)
+                            }
+                            _ => op.patch_file.get_url(&provider),
+                        };
+                        let Some(url) = url else {
+                            eprintln!(
+                                "\nNo URL found for provider {} for {} as {}",
+                                provider.key(),
+                                op.patch_file.path,
+                                payload.description()
+                            );
+                            continue;
+                        };
+                        match Source::resolve(url, self.manifest_origin.location.as_ref()) {
+                            Ok(source) => source,
+                            Err(e) => {
+                                eprintln!(
+                                    "\nCannot download {} as {}: {e}",
+                                    op.patch_file.path,
+                                    payload.description()
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                };
 
-                // Handle potential underflow
-                let total_amount_left =
-                    (total_download_size as u64).saturating_sub(total_size_downloaded);
+                if matches!(payload, Payload::Full) && self.is_segmented(op, &source) {
+                    let mut progress = FileProgress::new(idx + 1, self.pending_count(), op);
+                    let hash = self
+                        .download_segmented(
+                            http_client,
+                            &source,
+                            op,
+                            &mut progress,
+                            &mut total_size_downloaded,
+                            &progress_handler,
+                        )
+                        .await?;
+                    // Without range support the file is downloaded over a single connection
+                    if let Some(digest_str) = hash {
+                        if digest_str != op.patch_file.hash {
+                            eprintln!(
+                                "\nHash mismatch for {}: expected {}, got {}",
+                                op.patch_file.path, op.patch_file.hash, digest_str
+                            );
+                            break;
+                        }
+                        self.add_to_cache(op).await;
+                        installed = true;
+                        break;
+                    }
+                }
 
-                // Compute download speed and expected time left
-                let speed = downloaded as f64 / start.elapsed().as_secs_f64();
-                let expected_time_left = if speed > 0.0 {
-                    // Compute remaining time and cap at, say, 24 hours (86400 s).
-                    (total_amount_left as f64 / speed).min(86400.0)
-                } else {
-                    0.0
+                let opened = match payload {
+                    Payload::Chunks(indices) => {
+                        self.open_chunks(http_client, &provider, &op.patch_file, &source, indices)
+                    }
+                    Payload::Bundled(entry) => {
+                        source
+                            .open_range(http_client, entry.offset, entry.size)
+                            .await
+                    }
+                    _ => source.open(http_client).await,
+                };
+                let mut stream = match opened {
+                    Ok(stream) => stream,
+                    Err(TransportError::Unavailable(reason)) => {
+                        eprintln!("Failed to download {reason}");
+                        continue;
+                    }
+                    Err(e) if !matches!(payload, Payload::Full) => {
+                        eprintln!("Failed to download {}: {e}", source);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
                 };
 
-                let progress = Progress {
-                    current: downloaded,
-                    file_index: idx + 1,
-                    total_files: self.pending_count(),
-                    speed,
-                    file_size: file_size.try_into().unwrap(),
-                    elapsed: start.elapsed(),
-                    filename: dest_path
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string(),
-
-                    total_size_downloaded,
-                    total_amount_left,
-                    expected_time_left,
-                    total_download_size,
+                let mut decompressor = match payload {
+                    Payload::Compressed(compressed) => match Decompressor::new(compressed.encoding)
+                    {
+                        Ok(decompressor) => Some(decompressor),
+                        Err(e) => {
+                            eprintln!(
+                                "\nFailed to decompress {}, downloading the raw file: {e}",
+                                op.patch_file.path
+                            );
+                            continue;
+                        }
+                    },
+                    _ => None,
                 };
+                // Deltas are collected in memory and applied once complete, chunks are written over
+                // a copy of the installed file as they arrive. Both end up in a partial file next
+                // to the installed one, whole files are downloaded into the download cache if
+                // there is one. The partial file replaces the installed one once it matches the
+                // hash, which also leaves hard links to the installed file untouched.
+                let target_path = match payload {
+                    Payload::Delta(_) | Payload::Chunks(_) => partial_path(&dest_path),
+                    _ => self.download_path(op).await?,
+                };
+                let mut file = match payload {
+                    Payload::Delta(_) | Payload::Chunks(_) => None,
+                    _ => Some(tokio::fs::File::create(&target_path).await?),
+                };
+                let mut chunks = match payload {
+                    Payload::Chunks(indices) => Some(
+                        ChunkWriter::create(&dest_path, &target_path, &op.patch_file, indices)
+                            .await?,
+                    ),
+                    _ => None,
+                };
+                let mut patch = Vec::new();
+                let mut hasher = md5::Context::new();
+                // Progress counts the bytes transferred, before decompression
+                let mut progress = FileProgress::new(idx + 1, self.pending_count(), op);
+                progress.file_size = payload.size(&op.patch_file) as u64;
+                let mut corrupt = None;
+                // A compressed copy must not expand past the size of the file it stands for
+                let mut decompressed = 0u64;
 
-                progress_handler(&progress)?;
+                while let Some(chunk) = stream.next().await {
+                    let chunk = match chunk {
+                        Ok(chunk) => chunk,
+                        Err(e) if !matches!(payload, Payload::Full) => {
+                            corrupt = Some(e);
+                            break;
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
+                    match (file.as_mut(), decompressor.as_mut()) {
+                        (Some(file), Some(decompressor)) => match decompressor.decompress(&chunk) {
+                            Ok(contents) => {
+                                decompressed += contents.len() as u64;
+                                if decompressed > op.patch_file.size as u64 {
+                                    corrupt = Some(decompressed_too_large());
+                                    break;
+                                }
+                                file.write_all(&contents).await.map_err(|e| e.to_string())?;
+                                hasher.consume(&contents);
+                            }
+                            Err(e) => {
+                                corrupt = Some(e);
+                                break;
+                            }
+                        },
+                        (Some(file), None) => {
+                            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+                            hasher.consume(&chunk);
+                        }
+                        (None, _) => match chunks.as_mut() {
+                            Some(chunks) => {
+                                if !chunks.write(&chunk).await? {
+                                    corrupt = Some(std::io::Error::new(
+                                        std::io::ErrorKind::InvalidData,
+                                        "a chunk does not match its hash",
+                                    ));
+                                    break;
+                                }
+                            }
+                            None => patch.extend_from_slice(&chunk),
+                        },
+                    }
+                    progress.downloaded += chunk.len() as u64;
+                    total_size_downloaded += chunk.len() as u64;
+                    progress_handler(&progress.report(total_size_downloaded, total_download_size))?;
+                }
+
+                if let (Some(file), Some(decompressor)) = (file.as_mut(), decompressor) {
+                    if corrupt.is_none() {
+                        match decompressor.finish() {
+                            Ok(contents)
+                                if decompressed + contents.len() as u64
+                                    > op.patch_file.size as u64 =>
+                            {
+                                corrupt = Some(decompressed_too_large())
+                            }
+                            Ok(contents) => {
+                                file.write_all(&contents).await.map_err(|e| e.to_string())?;
+                                hasher.consume(&contents);
+                            }
+                            Err(e) => corrupt = Some(e),
+                        }
+                    }
+                }
+                if let Some(e) = corrupt {
+                    let _ = tokio::fs::remove_file(&target_path).await;
+                    eprintln!(
+                        "\nFailed to download {} as {}, trying the next source: {e}",
+                        op.patch_file.path,
+                        payload.description()
+                    );
+                    continue;
+                }
+
+                match (file, chunks) {
+                    (Some(mut file), _) => {
+                        file.flush().await?;
+                        drop(file);
+
+                        let digest_str = format!("{:x}", hasher.compute());
+                        if digest_str == op.patch_file.hash {
+                            self.install_download(op, &target_path).await?;
+                        } else {
+                            let _ = tokio::fs::remove_file(&target_path).await;
+                            eprintln!(
+                                "\nHash mismatch for {}: expected {}, got {}",
+                                op.patch_file.path, op.patch_file.hash, digest_str
+                            );
+                            if matches!(payload, Payload::Full) {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+                    (None, Some(chunks)) => {
+                        let rebuilt = chunks.finish(op.patch_file.size as u64).await?
+                            && hash_file(&target_path).await? == op.patch_file.hash;
+                        if rebuilt {
+                            tokio::fs::rename(&target_path, &dest_path).await?;
+                        } else {
+                            let _ = tokio::fs::remove_file(&target_path).await;
+                            eprintln!(
+                                "\nHash mismatch for {} after rebuilding it from chunks, downloading the full file",
+                                op.patch_file.path
+                            );
+                            continue;
+                        }
+                    }
+                    (None, None) => {
+                        // The target is streamed into a partial file and hashed while it is written
+                        let source = tokio::fs::read(&dest_path).await?;
+                        let applied = {
+                            let target_path = target_path.clone();
+                            tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+                                let file = std::fs::File::create(&target_path)?;
+                                let mut target = HashingWriter::new(std::io::BufWriter::new(file));
+                                super::delta::apply_to(&source, &patch, &mut target)?;
+                                target.finish()
+                            })
+                            .await?
+                        };
+                        match applied {
+                            Ok(digest_str) if digest_str == op.patch_file.hash => {
+                                tokio::fs::rename(&target_path, &dest_path).await?;
+                            }
+                            applied => {
+                                let _ = tokio::fs::remove_file(&target_path).await;
+                                match applied {
+                                    Ok(_) => eprintln!(
+                                        "\nHash mismatch for {} after applying the delta, downloading the full file",
+                                        op.patch_file.path
+                                    ),
+                                    Err(e) => eprintln!(
+                                        "\nDelta for {} could not be applied, downloading the full file: {e}",
+                                        op.patch_file.path
+                                    ),
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                }
+                self.add_to_cache(op).await;
+                installed = true;
+                break;
+            }
+
+            if !installed {
+                if self.options.bundle.is_some() && op.bundled.is_none() {
+                    eprintln!("\n{} is not in the bundle", op.patch_file.path);
+                }
+                failed.push(op.patch_file.path.clone());
+            }
+        }
+
+        if let Some(cache) = self.options.cache.clone() {
+            match tokio::task::spawn_blocking(move || cache.prune()).await? {
+                Ok(removed) if !removed.is_empty() => {
+                    println!("\rPruned {} files from the download cache", removed.len())
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("\nFailed to prune the download cache: {e}"),
             }
         }
+
+        if !failed.is_empty() {
+            return Err(format!(
+                "{} files could not be downloaded: {}",
+                failed.len(),
+                failed.join(", ")
+            )
+            .into());
+        }
         Ok(())
     }
+
+    /// Whether the whole file is downloaded in segments over several connections
+    fn is_segmented(&self, op: &FileOperation, source: &Source) -> bool {
+        self.options.segments > 1
+            && matches!(source, Source::Http(_))
+            && op.patch_file.size as u64 >= self.options.segment_threshold
+    }
+
+    /// Download a whole file over several connections, each fetching a byte range into its
+    /// place in a preallocated `.partial` file, and return the hash of the result.
+    ///
+    /// The partial file only replaces the installed file once its hash matches the manifest,
+    /// and is deleted otherwise. Returns `None` before anything is written if the server does
+    /// not answer range requests.
+    async fn download_segmented<F>(
+        &self,
+        http_client: &reqwest::Client,
+        source: &Source,
+        op: &FileOperation,
+        progress: &mut FileProgress,
+        total_size_downloaded: &mut u64,
+        progress_handler: &F,
+    ) -> Result<Option<String>, Box<dyn Error>>
+    where
+        F: Fn(&Progress) -> Result<(), Box<dyn Error>>,
+    {
+        let size = op.patch_file.size as u64;
+        let segment_count = self.options.segments.min(MAX_SEGMENTS) as u64;
+        let segment_size = size.div_ceil(segment_count).max(1);
+        let segments: Vec<(u64, u64)> = (0..size)
+            .step_by(segment_size as usize)
+            .map(|start| (start, segment_size.min(size - start)))
+            .collect();
+
+        let opened = futures::future::join_all(
+            segments
+                .iter()
+                .map(|&(start, len)| source.open_range(http_client, start, len)),
+        )
+        .await;
+        let mut streams = Vec::new();
+        for stream in opened {
+            match stream {
+                Ok(stream) => streams.push(stream),
+                Err(TransportError::Unavailable(_)) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let partial_path = self.download_path(op).await?;
+
+        let written: Result<String, Box<dyn Error>> = async {
+            let mut file = tokio::fs::File::create(&partial_path).await?;
+            file.set_len(size).await?;
+
+            // Segments are read concurrently, each chunk is written at the end of its segment so far
+            let mut positions: Vec<u64> = segments.iter().map(|&(start, _)| start).collect();
+            let mut chunks = futures::stream::select_all(
+                streams
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, stream)| stream.map(move |chunk| (index, chunk))),
+            );
+            let total_download_size = self.total_download_size();
+            while let Some((index, chunk)) = chunks.next().await {
+                let chunk = chunk?;
+                let (start, len) = segments[index];
+                if positions[index] + chunk.len() as u64 > start + len {
+                    return Err(format!(
+                        "{source} returned more data than requested for bytes {start}-{}",
+                        start + len - 1
+                    )
+                    .into());
+                }
+                file.seek(std::io::SeekFrom::Start(positions[index]))
+                    .await?;
+                file.write_all(&chunk).await?;
+                positions[index] += chunk.len() as u64;
+
+                progress.downloaded += chunk.len() as u64;
+                *total_size_downloaded += chunk.len() as u64;
+                progress_handler(&progress.report(*total_size_downloaded, total_download_size))?;
+            }
+            file.flush().await?;
+            Ok(hash_file(&partial_path).await?)
+        }
+        .await;
+
+        match written {
+            Ok(hash) if hash == op.patch_file.hash => {
+                self.install_download(op, &partial_path).await?;
+                Ok(Some(hash))
+            }
+            written => {
+                // Never leave a partly downloaded file of the right size behind
+                let _ = tokio::fs::remove_file(&partial_path).await;
+                written.map(Some)
+            }
+        }
+    }
+
+    /// Write the manifest and the files this transaction needs into an offline bundle. With
+    /// `all`, every planned file is included, not only pending ones.
+    ///
+    /// Files are gathered in a new staging directory next to the bundle. Up-to-date local files are
+    /// copied from the installation, the others are downloaded like [`Transaction::download`].
+    pub async fn export<F>(
+        &self,
+        http_client: &reqwest::Client,
+        progress_handler: F,
+        provider: Provider,
+        path: &Path,
+        all: bool,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(&Progress) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        let contents = self
+            .manifest_origin
+            .contents
+            .as_deref()
+            .ok_or("The manifest contents are not available for export")?;
+
+        let staging_dir = create_staging_dir(path).await?;
+        let exported = self
+            .stage_export(http_client, progress_handler, provider, &staging_dir, all)
+            .await;
+        let result = match exported {
+            Ok(files) => {
+                let (path, contents) = (path.to_path_buf(), contents.to_string());
+                let signature = self.manifest_origin.raw_signature.clone();
+                tokio::task::spawn_blocking(move || {
+                    Bundle::write(&path, &contents, signature.as_deref(), &files)
+                })
+                .await?
+                .map_err(Into::into)
+            }
+            Err(e) => Err(e),
+        };
+        tokio::fs::remove_dir_all(&staging_dir).await?;
+        result
+    }
+
+    /// Gather the files to export in `staging_dir` and return their hashes and paths
+    async fn stage_export<F>(
+        &self,
+        http_client: &reqwest::Client,
+        progress_handler: F,
+        provider: Provider,
+        staging_dir: &Path,
+        all: bool,
+    ) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>>
+    where
+        F: Fn(&Progress) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        let mut staging = Transaction {
+            operations: Vec::new(),
+            optional_groups: Vec::new(),
+            manifest_version: self.manifest_version.clone(),
+            manifest_uid: self.manifest_uid.clone(),
+            manifest_origin: self.manifest_origin.clone(),
+            base_path: staging_dir.to_path_buf(),
+            channel: None,
+            options: TransactionOptions {
+                cache: self.options.cache.clone(),
+                segments: self.options.segments,
+                segment_threshold: self.options.segment_threshold,
+                ..Default::default()
+            },
+        };
+        let mut files = Vec::new();
+        for op in &self.operations {
+            let needed = match op.status {
+                Status::OutOfDate | Status::Missing => true,
+                Status::Present | Status::Kept => all,
+                Status::Ignored | Status::Remove => false,
+            };
+            if !needed {
+                continue;
+            }
+            let staged = staging_dir.join(&op.patch_file.path);
+            files.push((op.patch_file.hash.clone(), staged.clone()));
+
+            let local = self.base_path.join(&op.patch_file.path);
+            if op.status == Status::Present && hash_file(&local).await? == op.patch_file.hash {
+                if let Some(dir) = staged.parent() {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                tokio::fs::copy(&local, &staged).await?;
+                continue;
+            }
+            staging.operations.push(FileOperation {
+                cached: staging
+                    .options
+                    .cache
+                    .as_ref()
+                    .is_some_and(|cache| cache.contains(&op.patch_file.hash)),
+                ..FileOperation::new(Status::Missing, &op.patch_file, 0)
+            });
+        }
+
+        staging
+            .download(http_client, progress_handler, provider)
+            .await?;
+        Ok(files)
+    }
+}
+
+/// Progress of the file being downloaded, reported with the totals of the transaction
+struct FileProgress {
+    file_index: usize,
+    total_files: usize,
+    filename: String,
+    file_size: u64,
+    /// Bytes of this file transferred so far
+    downloaded: u64,
+    start: std::time::Instant,
+}
+
+impl FileProgress {
+    fn new(file_index: usize, total_files: usize, op: &FileOperation) -> Self {
+        FileProgress {
+            file_index,
+            total_files,
+            filename: Path::new(&op.patch_file.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            file_size: op.patch_file.size as u64,
+            downloaded: 0,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn report(&self, total_size_downloaded: u64, total_download_size: i64) -> Progress {
+        // Handle potential underflow
+        let total_amount_left = (total_download_size as u64).saturating_sub(total_size_downloaded);
+
+        // Compute download speed and expected time left
+        let speed = self.downloaded as f64 / self.start.elapsed().as_secs_f64();
+        let expected_time_left = if speed > 0.0 {
+            // Compute remaining time and cap at, say, 24 hours (86400 s).
+            (total_amount_left as f64 / speed).min(86400.0)
+        } else {
+            0.0
+        };
+
+        Progress {
+            current: self.downloaded,
+            file_index: self.file_index,
+            total_files: self.total_files,
+            speed,
+            file_size: self.file_size,
+            elapsed: self.start.elapsed(),
+            filename: self.filename.clone(),
+            total_size_downloaded,
+            total_amount_left,
+            expected_time_left,
+            total_download_size,
+        }
+    }
+}
+
+/// Hash a file on the blocking thread pool
+async fn hash_file(path: &Path) -> std::io::Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || super::manifest::hash_file(&path)).await?
+}
+
+/// Create a new, empty directory next to `path` for staging its contents. Existing
+/// directories are never reused, they may belong to another run or to the user.
+async fn create_staging_dir(path: &Path) -> std::io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let mut dir = path.as_os_str().to_os_string();
+        dir.push(format!(".staging-{}-{attempt}", std::process::id()));
+        let dir = PathBuf::from(dir);
+        match tokio::fs::create_dir(&dir).await {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Error for a compressed copy that decompresses to more than the size of its file
+fn decompressed_too_large() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "the compressed copy is larger than the file once decompressed",
+    )
+}
+
+/// Temporary file a download is written to until it is verified and replaces `path`
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_os_string();
+    partial_path.push(".partial");
+    PathBuf::from(partial_path)
+}
+
+/// Writer computing the MD5 hash of everything written through it
+struct HashingWriter<W: std::io::Write> {
+    inner: W,
+    hasher: md5::Context,
+}
+
+impl<W: std::io::Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: md5::Context::new(),
+        }
+    }
+
+    /// Flush the inner writer and return the hash of the written data
+    fn finish(mut self) -> std::io::Result<String> {
+        self.inner.flush()?;
+        Ok(format!("{:x}", self.hasher.compute()))
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.consume(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a stream of chunks over a copy of the installed file, each at its offset, and checks
+/// every chunk against its hash as soon as it is complete
+struct ChunkWriter<'a> {
+    output: tokio::fs::File,
+    chunks: Vec<&'a Chunk>,
+    /// Chunk being written and how many of its bytes were written so far
+    current: usize,
+    written: u64,
+    hasher: md5::Context,
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Copy the installed file to `path` to write the chunks with the given indices into
+    async fn create(
+        installed: &Path,
+        path: &Path,
+        file: &'a PatchFile,
+        indices: &[usize],
+    ) -> std::io::Result<Self> {
+        tokio::fs::copy(installed, path).await?;
+        Ok(ChunkWriter {
+            output: tokio::fs::OpenOptions::new().write(true).open(path).await?,
+            chunks: indices.iter().map(|&index| &file.chunks[index]).collect(),
+            current: 0,
+            written: 0,
+            hasher: md5::Context::new(),
+        })
+    }
+
+    /// Write the next bytes of the stream. Returns false if they do not match the chunks.
+    async fn write(&mut self, mut data: &[u8]) -> std::io::Result<bool> {
+        while !data.is_empty() {
+            let Some(chunk) = self.chunks.get(self.current) else {
+                return Ok(false);
+            };
+            if self.written == 0 {
+                self.output
+                    .seek(std::io::SeekFrom::Start(chunk.offset))
+                    .await?;
+            }
+            let len = data.len().min((chunk.size - self.written) as usize);
+            self.output.write_all(&data[..len]).await?;
+            self.hasher.consume(&data[..len]);
+            self.written += len as u64;
+            data = &data[len..];
+
+            if self.written == chunk.size {
+                let hasher = std::mem::replace(&mut self.hasher, md5::Context::new());
+                if format!("{:x}", hasher.compute()) != chunk.hash {
+                    return Ok(false);
+                }
+                self.current += 1;
+                self.written = 0;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Truncate the copy to the file size and flush it. Returns false if the stream ended before
+    /// the last chunk.
+    async fn finish(mut self, size: u64) -> std::io::Result<bool> {
+        if self.current != self.chunks.len() {
+            return Ok(false);
+        }
+        self.output.set_len(size).await?;
+        self.output.flush().await?;
+        Ok(true)
+    }
 }
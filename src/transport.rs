@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use url::Url;
+
+use super::manifest::Location;
+
+/// Size of the buffer used when streaming local files
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stream of downloaded bytes
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+#[derive(Debug)]
+pub enum TransportError {
+    /// The file is not available from this source, e.g. an HTTP error status or a missing local file
+    Unavailable(String),
+    /// The source could not be reached or read
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Unavailable(reason) => write!(f, "{reason}"),
+            TransportError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where a patch file is downloaded from
+pub enum Source {
+    Http(Url),
+    File(PathBuf),
+}
+
+impl Source {
+    /// Resolve a URL listed in the manifest.
+    ///
+    /// Accepts `http(s)://` and `file://` URLs, and paths. Relative references are resolved
+    /// against the manifest location, or the current directory if the manifest came from stdin.
+    pub fn resolve(reference: &str, manifest_location: Option<&Location>) -> Result<Self, String> {
+        match Url::parse(reference) {
+            // Single letter schemes are Windows drive letters, not URLs
+            Ok(url) if url.scheme().len() > 1 => match url.scheme() {
+                "http" | "https" => Ok(Source::Http(url)),
+                "file" => url
+                    .to_file_path()
+                    .map(Source::File)
+                    .map_err(|_| format!("Invalid file URL: {reference}")),
+                scheme => Err(format!("Unsupported URL scheme '{scheme}' in {reference}")),
+            },
+            _ => match manifest_location {
+                Some(Location::Url(base)) => base
+                    .join(reference)
+                    .map(Source::Http)
+                    .map_err(|e| format!("Invalid relative URL {reference}: {e}")),
+                Some(Location::FilePath(path)) => Ok(Source::File(
+                    path.parent()
+                        .unwrap_or(std::path::Path::new(""))
+                        .join(reference),
+                )),
+                Some(Location::Stdin) | None => Ok(Source::File(PathBuf::from(reference))),
+            },
+        }
+    }
+
+    /// Open the source for streaming
+    pub async fn open(&self, client: &reqwest::Client) -> Result<ByteStream, TransportError> {
+        match self {
+            Source::Http(url) => {
+                let response = client
+                    .get(url.as_str())
+                    .send()
+                    .await
+                    .map_err(|e| TransportError::Failed(e.into()))?;
+                if !response.status().is_success() {
+                    return Err(TransportError::Unavailable(format!(
+                        "{}: {}",
+                        url,
+                        response.status()
+                    )));
+                }
+                Ok(response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map_err(std::io::Error::other))
+                    .boxed())
+            }
+            Source::File(path) => Ok(read_stream(open_file(path).await?)),
+        }
+    }
+
+    /// Open `size` bytes starting at `offset` for streaming, using a range request for HTTP
+    pub async fn open_range(
+        &self,
+        client: &reqwest::Client,
+        offset: u64,
+        size: u64,
+    ) -> Result<ByteStream, TransportError> {
+        // An empty range has no valid range header
+        if size == 0 {
+            return Ok(futures::stream::empty().boxed());
+        }
+        match self {
+            Source::Http(url) => {
+                let response = client
+                    .get(url.as_str())
+                    .header(RANGE, format!("bytes={}-{}", offset, offset + size - 1))
+                    .send()
+                    .await
+                    .map_err(|e| TransportError::Failed(e.into()))?;
+                if response.status() != StatusCode::PARTIAL_CONTENT {
+                    return Err(TransportError::Unavailable(format!(
+                        "{}: range request answered with {}",
+                        url,
+                        response.status()
+                    )));
+                }
+                Ok(response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map_err(std::io::Error::other))
+                    .boxed())
+            }
+            Source::File(path) => {
+                let mut file = open_file(path).await?;
+                file.seek(std::io::SeekFrom::Start(offset))
+                    .await
+                    .map_err(|e| TransportError::Failed(e.into()))?;
+                Ok(read_stream(file.take(size)))
+            }
+        }
+    }
+}
+
+async fn open_file(path: &Path) -> Result<tokio::fs::File, TransportError> {
+    tokio::fs::File::open(path)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                TransportError::Unavailable(format!("{}: not found", path.display()))
+            }
+            _ => TransportError::Failed(e.into()),
+        })
+}
+
+/// Stream a reader in chunks of `FILE_CHUNK_SIZE`
+fn read_stream<R: AsyncRead + Unpin + Send + 'static>(reader: R) -> ByteStream {
+    futures::stream::unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0u8; FILE_CHUNK_SIZE];
+        match reader.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok(Bytes::from(buffer)), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    })
+    .boxed()
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Http(url) => write!(f, "{url}"),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use std::env::temp_dir;
-use std::fs::{remove_file, File};
+use std::fs::{create_dir_all, remove_dir_all, remove_file, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use rs_manifest_patcher::manifest::Manifest;
+use serde_json::{json, Value};
 
 pub struct TempFile {
     pub path: PathBuf,
@@ -26,6 +29,238 @@ impl Drop for TempFile {
     }
 }
 
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(dirname: &str) -> Self {
+        // Test binaries run in parallel and share `mod common`, so keep directories per process
+        let mut path = temp_dir();
+        path.push(format!("{dirname}_{}", std::process::id()));
+
+        if path.exists() {
+            remove_dir_all(&path).expect("Failed to clear temp dir");
+        }
+        create_dir_all(&path).expect("Failed to create temp dir");
+
+        TempDir { path }
+    }
+
+    /// Create a file relative to the directory, including parent directories
+    pub fn write(&self, relative_path: &str, content: &[u8]) -> PathBuf {
+        let path = self.path.join(relative_path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).expect("Failed to create parent dirs");
+        }
+        let mut file = File::create(&path).expect("Failed to create file");
+        file.write_all(content).expect("Failed to write file");
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        remove_dir_all(&self.path).expect("Failed to delete temp dir");
+    }
+}
+
+/// UID of the manifests built by [`manifest_with`]
+pub const UID: &str = "5a63cd8c-956c-48a0-95ae-7e41d1e73182";
+
+/// MD5 of `contents` as written in manifests
+pub fn hash(contents: &[u8]) -> String {
+    format!("{:x}", md5::compute(contents))
+}
+
+/// Local path written as a manifest URL
+pub fn url(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Manifest entry for `path` with `contents`, downloaded from `url`
+pub fn file(path: &str, contents: &[u8], url: &str) -> Value {
+    json!({
+        "Path": path,
+        "Hash": hash(contents),
+        "Size": contents.len(),
+        "Custom": false,
+        "Urls": { "none": url },
+    })
+}
+
+/// Manifest entry like [`file`] with further fields, e.g. `Custom`, `Locale` or `Deltas`
+pub fn file_with(path: &str, contents: &[u8], url: &str, fields: Value) -> Value {
+    let mut entry = file(path, contents, url);
+    if let (Value::Object(entry), Value::Object(fields)) = (&mut entry, fields) {
+        entry.extend(fields);
+    }
+    entry
+}
+
+/// Manifest JSON with [`UID`] and the given file entries
+pub fn manifest_json(files: &[Value]) -> String {
+    json!({ "Version": "1.1", "Uid": UID, "Files": files }).to_string()
+}
+
+/// Manifest with [`UID`] and the given file entries
+pub fn manifest_with(files: &[Value]) -> Manifest {
+    Manifest::from_json(&manifest_json(files)).unwrap()
+}
+
+/// Minimal HTTP/1.1 request as seen by [`TestServer`]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: std::collections::HashMap<String, String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Delay before the response is written, to simulate slow servers
+    pub delay: std::time::Duration,
+}
+
+impl Response {
+    pub fn ok(body: &[u8]) -> Self {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.to_vec(),
+            delay: std::time::Duration::ZERO,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Response {
+            status: 404,
+            ..Response::ok(b"")
+        }
+    }
+
+    /// Respond with the full body, or the requested part of it if the request has a `Range` header
+    pub fn ranged(request: &Request, body: &[u8]) -> Self {
+        let range = request
+            .headers
+            .get("range")
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.split_once('-'));
+        match range {
+            Some((start, end)) => {
+                let start: usize = start.parse().unwrap_or(0);
+                let end: usize = end
+                    .parse()
+                    .map(|end: usize| end.min(body.len() - 1))
+                    .unwrap_or(body.len() - 1);
+                Response {
+                    status: 206,
+                    headers: vec![(
+                        "Content-Range".to_string(),
+                        format!("bytes {start}-{end}/{}", body.len()),
+                    )],
+                    ..Response::ok(&body[start..=end])
+                }
+            }
+            None => Response::ok(body),
+        }
+    }
+}
+
+type Handler = std::sync::Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Local HTTP stand-in for manifest and file servers
+pub struct TestServer {
+    pub addr: std::net::SocketAddr,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind test server");
+        let addr = listener.local_addr().unwrap();
+        let handler: Handler = std::sync::Arc::new(handler);
+
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let _ = Self::handle_connection(stream, handler).await;
+                });
+            }
+        });
+
+        TestServer { addr, handle }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    async fn handle_connection(
+        stream: tokio::net::TcpStream,
+        handler: Handler,
+    ) -> std::io::Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = std::collections::HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        let request = Request {
+            method,
+            path,
+            headers,
+        };
+        let response = handler(&request);
+        tokio::time::sleep(response.delay).await;
+
+        let mut head = format!(
+            "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let mut stream = reader.into_inner();
+        stream.write_all(head.as_bytes()).await?;
+        if request.method != "HEAD" {
+            stream.write_all(&response.body).await?;
+        }
+        stream.shutdown().await
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,4 +269,50 @@ mod tests {
     fn test_tempfile_creation() {
         let _temp = TempFile::new("test.txt", "Hello, world!");
     }
+
+    #[test]
+    fn test_tempdir_creation() {
+        let temp = TempDir::new("test_tempdir");
+        let file = temp.write("nested/file.txt", b"Hello, world!");
+        assert!(file.is_file());
+    }
+
+    #[test]
+    fn test_manifest_helpers() {
+        let manifest = manifest_with(&[file_with(
+            "Data/A.bin",
+            b"patch data",
+            &url(Path::new("mirror/A.bin")),
+            json!({ "Custom": true }),
+        )]);
+        assert_eq!(manifest.uid, UID);
+        assert_eq!(manifest.files[0].hash, hash(b"patch data"));
+        assert_eq!(manifest.files[0].size, 10);
+        assert!(manifest.files[0].custom);
+    }
+
+    #[tokio::test]
+    async fn test_server_serves_ranges() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/data" => Response::ranged(request, b"0123456789"),
+            _ => Response::not_found(),
+        })
+        .await;
+
+        let client = reqwest::Client::new();
+        let full = client.get(server.url("/data")).send().await.unwrap();
+        assert_eq!(full.bytes().await.unwrap().as_ref(), b"0123456789");
+
+        let part = client
+            .get(server.url("/data"))
+            .header("Range", "bytes=2-4")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(part.status().as_u16(), 206);
+        assert_eq!(part.bytes().await.unwrap().as_ref(), b"234");
+
+        let missing = client.get(server.url("/missing")).send().await.unwrap();
+        assert_eq!(missing.status().as_u16(), 404);
+    }
 }
@@ -0,0 +1,260 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use common::{file, hash, TempDir};
+use ed25519_dalek::{Signer, SigningKey};
+use rs_manifest_patcher::bundle::Bundle;
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest, Provider};
+use rs_manifest_patcher::signature::{SignatureStatus, TrustedKeys};
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+/// Manifest JSON for `Data/A.bin` and `Data/B.bin`, downloaded from `A.bin` and `B.bin`
+fn manifest_json() -> String {
+    common::manifest_json(&[
+        file("Data/A.bin", b"patch data", "A.bin"),
+        file("Data/B.bin", b"other data", "B.bin"),
+    ])
+}
+
+/// Write the manifest and its files to `mirror` and load it as a manifest file
+async fn mirror_manifest(mirror: &TempDir, trust: TrustedKeys) -> Manifest {
+    mirror.write("A.bin", b"patch data");
+    mirror.write("B.bin", b"other data");
+    let path = mirror.write("manifest.json", manifest_json().as_bytes());
+    let options = BuildOptions {
+        trust,
+        ..BuildOptions::default()
+    };
+    Manifest::build(&Location::FilePath(path), &options)
+        .await
+        .unwrap()
+}
+
+fn allow_unsigned() -> TrustedKeys {
+    let mut trust = TrustedKeys::default();
+    trust.allow_unsigned = true;
+    trust
+}
+
+fn bundle_options(bundle: &std::path::Path) -> TransactionOptions {
+    TransactionOptions {
+        bundle: Some(Bundle::open(bundle).unwrap()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exports_pending_files() {
+        let mirror = TempDir::new("test_bundle_pending_mirror");
+        let install = TempDir::new("test_bundle_pending_install");
+        let out = TempDir::new("test_bundle_pending_out");
+        install.write("Data/A.bin", b"patch data");
+        out.write("patch.tar.staging/keep.txt", b"user data");
+        let path = out.path.join("patch.tar");
+
+        let transaction = Transaction::new(
+            mirror_manifest(&mirror, allow_unsigned()).await,
+            install.path.clone(),
+        );
+        transaction
+            .export(
+                &reqwest::Client::new(),
+                |_| Ok(()),
+                Provider::None,
+                &path,
+                false,
+            )
+            .await
+            .unwrap();
+
+        let bundle = Bundle::open(&path).unwrap();
+        assert!(bundle.file(&hash(b"patch data")).is_none());
+        assert_eq!(bundle.file(&hash(b"other data")).unwrap().size, 10);
+        // The staging directory is removed, an unrelated one with the old name is left alone
+        let entries: Vec<_> = std::fs::read_dir(&out.path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            std::fs::read(out.path.join("patch.tar.staging/keep.txt")).unwrap(),
+            b"user data"
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_without_the_original_sources() {
+        let mirror = TempDir::new("test_bundle_import_mirror");
+        let install = TempDir::new("test_bundle_import_install");
+        let out = TempDir::new("test_bundle_import_out");
+        install.write("Data/A.bin", b"patch data");
+        let path = out.path.join("patch.tar");
+
+        // Export everything, including the file that is already installed
+        let transaction = Transaction::new(
+            mirror_manifest(&mirror, allow_unsigned()).await,
+            install.path.clone(),
+        );
+        transaction
+            .export(
+                &reqwest::Client::new(),
+                |_| Ok(()),
+                Provider::None,
+                &path,
+                true,
+            )
+            .await
+            .unwrap();
+        std::fs::remove_file(mirror.path.join("A.bin")).unwrap();
+        std::fs::remove_file(mirror.path.join("B.bin")).unwrap();
+
+        let offline = TempDir::new("test_bundle_import_offline");
+        let bundle = Bundle::open(&path).unwrap();
+        let manifest = bundle.manifest(&allow_unsigned()).unwrap();
+        let transaction =
+            Transaction::with_options(manifest, offline.path.clone(), bundle_options(&path));
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(offline.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+        assert_eq!(
+            std::fs::read(offline.path.join("Data/B.bin")).unwrap(),
+            b"other data"
+        );
+    }
+
+    #[tokio::test]
+    async fn corrupt_bundled_file_is_rejected() {
+        let out = TempDir::new("test_bundle_corrupt_out");
+        let install = TempDir::new("test_bundle_corrupt_install");
+        install.write("Data/A.bin", b"older data");
+        let file = out.write("staged.bin", b"corrupted!");
+        let path = out.path.join("patch.tar");
+        Bundle::write(
+            &path,
+            &manifest_json(),
+            None,
+            &[
+                (hash(b"patch data"), file.clone()),
+                (hash(b"other data"), file),
+            ],
+        )
+        .unwrap();
+
+        let manifest = Bundle::open(&path)
+            .unwrap()
+            .manifest(&allow_unsigned())
+            .unwrap();
+        let transaction =
+            Transaction::with_options(manifest, install.path.clone(), bundle_options(&path));
+        let result = transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await;
+        assert!(result.is_err());
+
+        // The installed file is only replaced once a bundled entry matches its hash
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            b"older data"
+        );
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+        assert!(!install.path.join("Data/B.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn import_reports_files_missing_from_the_bundle() {
+        let mirror = TempDir::new("test_bundle_missing_mirror");
+        let install = TempDir::new("test_bundle_missing_install");
+        let target = TempDir::new("test_bundle_missing_target");
+        let out = TempDir::new("test_bundle_missing_out");
+        install.write("Data/A.bin", b"patch data");
+        let path = out.path.join("patch.tar");
+
+        // Only B.bin is pending, so A.bin is not exported
+        let manifest = mirror_manifest(&mirror, allow_unsigned()).await;
+        Transaction::new(manifest.clone(), install.path.clone())
+            .export(
+                &reqwest::Client::new(),
+                |_| Ok(()),
+                Provider::None,
+                &path,
+                false,
+            )
+            .await
+            .unwrap();
+
+        // The original sources are still reachable but must not be used
+        let transaction =
+            Transaction::with_options(manifest, target.path.clone(), bundle_options(&path));
+        let error = transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Data/A.bin"));
+        assert!(!target.path.join("Data/A.bin").exists());
+        assert_eq!(
+            std::fs::read(target.path.join("Data/B.bin")).unwrap(),
+            b"other data"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_the_manifest_signature() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut trust = TrustedKeys::default();
+        trust
+            .add_key(&BASE64.encode(key.verifying_key().to_bytes()))
+            .unwrap();
+
+        let mirror = TempDir::new("test_bundle_signature_mirror");
+        let install = TempDir::new("test_bundle_signature_install");
+        let out = TempDir::new("test_bundle_signature_out");
+        let signature = BASE64.encode(key.sign(manifest_json().as_bytes()).to_bytes());
+        mirror.write("manifest.json.sig", signature.as_bytes());
+        let path = out.path.join("patch.tar");
+
+        let transaction = Transaction::new(
+            mirror_manifest(&mirror, trust.clone()).await,
+            install.path.clone(),
+        );
+        transaction
+            .export(
+                &reqwest::Client::new(),
+                |_| Ok(()),
+                Provider::None,
+                &path,
+                false,
+            )
+            .await
+            .unwrap();
+
+        let bundle = Bundle::open(&path).unwrap();
+        assert_eq!(
+            bundle.manifest(&trust).unwrap().origin.signature,
+            SignatureStatus::Verified
+        );
+        // The bundled signature is still checked against the trusted keys
+        let mut other = TrustedKeys::default();
+        other
+            .add_key(
+                &BASE64.encode(
+                    SigningKey::from_bytes(&[8u8; 32])
+                        .verifying_key()
+                        .to_bytes(),
+                ),
+            )
+            .unwrap();
+        assert!(bundle.manifest(&other).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rs_manifest_patcher::channel::{Channel, DEFAULT_MANIFEST_TEMPLATE};
+    use rs_manifest_patcher::manifest::Location;
+
+    #[test]
+    fn parse_known_and_custom_channels() {
+        assert_eq!(Channel::from_str("production"), Ok(Channel::Production));
+        assert_eq!(Channel::from_str("ptr"), Ok(Channel::Ptr));
+        assert_eq!(
+            Channel::from_str("dev-2"),
+            Ok(Channel::Custom("dev-2".to_string()))
+        );
+    }
+
+    #[test]
+    fn reject_unsafe_custom_channel() {
+        assert!(Channel::from_str("").is_err());
+        assert!(Channel::from_str("a&b=c").is_err());
+    }
+
+    #[test]
+    fn build_location_from_template() {
+        let location = Channel::Staging
+            .manifest_location(DEFAULT_MANIFEST_TEMPLATE)
+            .unwrap();
+        match location {
+            Location::Url(url) => assert_eq!(url.query(), Some("environment=staging")),
+            _ => panic!("Expected a URL location"),
+        }
+    }
+
+    #[test]
+    fn template_requires_placeholder() {
+        let result = Channel::Ptr.manifest_location("http://localhost:8080/manifest.json");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn serde_uses_channel_key() {
+        let json = serde_json::to_string(&Channel::Custom("beta".to_string())).unwrap();
+        assert_eq!(json, "\"beta\"");
+        let channel: Channel = serde_json::from_str("\"ptr\"").unwrap();
+        assert_eq!(channel, Channel::Ptr);
+    }
+}
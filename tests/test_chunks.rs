@@ -0,0 +1,197 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{file_with, hash, manifest_with, url, Response, TempDir, TestServer};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+const CHUNK_SIZE: usize = 16;
+
+/// Four chunks, the second and last one differ between versions
+fn versions() -> (Vec<u8>, Vec<u8>) {
+    let old = b"AAAAAAAAAAAAAAAABBBBBBBBBBBBBBBBCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDD".to_vec();
+    let mut new = old.clone();
+    new[CHUNK_SIZE..2 * CHUNK_SIZE].copy_from_slice(b"bbbbbbbbbbbbbbbb");
+    new[3 * CHUNK_SIZE..].copy_from_slice(b"dddddddddddddddd");
+    (old, new)
+}
+
+/// Manifest for `contents` served from `file_url`, `chunk_urls` lists a URL per chunk if any
+fn manifest(contents: &[u8], file_url: &str, chunk_urls: Option<Vec<String>>) -> Manifest {
+    let chunks: Vec<_> = contents
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut entry = json!({
+                "Offset": index * CHUNK_SIZE,
+                "Size": chunk.len(),
+                "Hash": hash(chunk),
+            });
+            if let Some(urls) = &chunk_urls {
+                entry["Urls"] = json!({ "none": urls[index] });
+            }
+            entry
+        })
+        .collect();
+    manifest_with(&[file_with(
+        "Data/A.bin",
+        contents,
+        file_url,
+        json!({ "Chunks": chunks }),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_only_changed_chunks() {
+        let (old, new) = versions();
+        let install = TempDir::new("test_chunks_plan");
+        install.write("Data/A.bin", &old);
+
+        let transaction = Transaction::new(manifest(&new, "A.bin", None), install.path.clone());
+        let report = transaction.generate_report();
+        let chunks = report.outdated_files[0].chunks.as_ref().unwrap();
+        assert_eq!((chunks.changed, chunks.total), (2, 4));
+        assert_eq!(chunks.size, 2 * CHUNK_SIZE as i64);
+        assert_eq!(report.total_download_size, 2 * CHUNK_SIZE as u64);
+    }
+
+    #[test]
+    fn missing_file_downloads_whole_file() {
+        let (_, new) = versions();
+        let install = TempDir::new("test_chunks_missing");
+
+        let transaction = Transaction::new(manifest(&new, "A.bin", None), install.path.clone());
+        let report = transaction.generate_report();
+        assert_eq!(report.total_download_size, new.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_file_from_local_ranges() {
+        let (old, new) = versions();
+        let mirror = TempDir::new("test_chunks_ranges_mirror");
+        let install = TempDir::new("test_chunks_ranges_install");
+        let full = mirror.write("A.bin", &new);
+        install.write("Data/A.bin", &old);
+
+        let transaction = Transaction::new(manifest(&new, &url(&full), None), install.path.clone());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), new);
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn rebuilds_file_from_chunk_urls() {
+        let (old, new) = versions();
+        let mirror = TempDir::new("test_chunks_urls_mirror");
+        let install = TempDir::new("test_chunks_urls_install");
+        let chunk_urls = new
+            .chunks(CHUNK_SIZE)
+            .enumerate()
+            .map(|(index, chunk)| url(&mirror.write(&format!("A.bin.{index}"), chunk)))
+            .collect();
+        install.write("Data/A.bin", &old);
+
+        // The whole file is not on the mirror, only the chunks
+        let transaction = Transaction::new(
+            manifest(&new, &url(&mirror.path.join("A.bin")), Some(chunk_urls)),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), new);
+    }
+
+    #[tokio::test]
+    async fn corrupt_chunk_falls_back_to_whole_file() {
+        let (old, new) = versions();
+        let mirror = TempDir::new("test_chunks_corrupt_mirror");
+        let install = TempDir::new("test_chunks_corrupt_install");
+        let full = mirror.write("A.bin", &new);
+        let chunk_urls = (0..4)
+            .map(|index| url(&mirror.write(&format!("A.bin.{index}"), b"corrupt chunk!!!")))
+            .collect();
+        install.write("Data/A.bin", &old);
+
+        let transaction = Transaction::new(
+            manifest(&new, &url(&full), Some(chunk_urls)),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), new);
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn requests_changed_chunks_by_range() {
+        let (old, new) = versions();
+        let install = TempDir::new("test_chunks_http");
+        install.write("Data/A.bin", &old);
+
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let served = new.clone();
+        let seen = ranges.clone();
+        let server = TestServer::start(move |request| {
+            seen.lock()
+                .unwrap()
+                .push(request.headers.get("range").cloned());
+            Response::ranged(request, &served)
+        })
+        .await;
+
+        let transaction = Transaction::new(
+            manifest(&new, &server.url("/A.bin"), None),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), new);
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            vec![
+                Some("bytes=16-31".to_string()),
+                Some("bytes=48-63".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_without_range_support_falls_back() {
+        let (old, new) = versions();
+        let install = TempDir::new("test_chunks_no_ranges");
+        install.write("Data/A.bin", &old);
+
+        let served = new.clone();
+        let server = TestServer::start(move |_| Response::ok(&served)).await;
+
+        let transaction = Transaction::new(
+            manifest(&new, &server.url("/A.bin"), None),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), new);
+    }
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use common::{file_with, manifest_with, url, TempDir};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+fn contents() -> Vec<u8> {
+    b"patch data ".repeat(1000)
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Manifest for `contents()` with a compressed copy served from `mirror`
+fn manifest(mirror: &TempDir, encoding: &str, compressed: &[u8]) -> Manifest {
+    let raw = mirror.write("A.bin", &contents());
+    let copy = mirror.write("A.bin.compressed", compressed);
+    manifest_with(&[file_with(
+        "Data/A.bin",
+        &contents(),
+        &url(&raw),
+        json!({
+            "Compressed": {
+                "Encoding": encoding,
+                "Size": compressed.len(),
+                "Urls": { "none": url(&copy) },
+            },
+        }),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn downloads_zstd_copy() {
+        let mirror = TempDir::new("test_compression_zstd_mirror");
+        let install = TempDir::new("test_compression_zstd_install");
+        let compressed = zstd::encode_all(contents().as_slice(), 3).unwrap();
+
+        let transaction =
+            Transaction::new(manifest(&mirror, "zstd", &compressed), install.path.clone());
+        let report = transaction.generate_report();
+        assert_eq!(report.total_inbound_size, contents().len() as u64);
+        assert_eq!(report.total_download_size, compressed.len() as u64);
+
+        // Only the compressed copy is needed, progress counts compressed bytes
+        std::fs::remove_file(mirror.path.join("A.bin")).unwrap();
+        let transferred = Arc::new(AtomicU64::new(0));
+        let counter = transferred.clone();
+        transaction
+            .download(
+                &reqwest::Client::new(),
+                move |progress| {
+                    counter.store(progress.total_size_downloaded, Ordering::SeqCst);
+                    Ok(())
+                },
+                Provider::None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            contents()
+        );
+        assert_eq!(transferred.load(Ordering::SeqCst), compressed.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn downloads_gzip_copy() {
+        let mirror = TempDir::new("test_compression_gzip_mirror");
+        let install = TempDir::new("test_compression_gzip_install");
+
+        let transaction = Transaction::new(
+            manifest(&mirror, "gzip", &gzip(&contents())),
+            install.path.clone(),
+        );
+        std::fs::remove_file(mirror.path.join("A.bin")).unwrap();
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            contents()
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_raw_file() {
+        let mirror = TempDir::new("test_compression_fallback_mirror");
+        let install = TempDir::new("test_compression_fallback_install");
+
+        // Truncated stream
+        let compressed = zstd::encode_all(contents().as_slice(), 3).unwrap();
+        let truncated = &compressed[..compressed.len() / 2];
+        let transaction =
+            Transaction::new(manifest(&mirror, "zstd", truncated), install.path.clone());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            contents()
+        );
+
+        // Valid stream of the wrong contents
+        std::fs::remove_file(install.path.join("Data/A.bin")).unwrap();
+        let transaction = Transaction::new(
+            manifest(&mirror, "gzip", &gzip(b"something else")),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            contents()
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_copy_is_not_decompressed_past_the_file_size() {
+        let mirror = TempDir::new("test_compression_oversized_mirror");
+        let install = TempDir::new("test_compression_oversized_install");
+
+        // Barely compressible data, many times larger than the file
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..1024 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let compressed = zstd::encode_all(noise.as_slice(), 3).unwrap();
+        let transaction =
+            Transaction::new(manifest(&mirror, "zstd", &compressed), install.path.clone());
+        std::fs::remove_file(mirror.path.join("A.bin")).unwrap();
+
+        let transferred = Arc::new(AtomicU64::new(0));
+        let counter = transferred.clone();
+        let result = transaction
+            .download(
+                &reqwest::Client::new(),
+                move |progress| {
+                    counter.store(progress.total_size_downloaded, Ordering::SeqCst);
+                    Ok(())
+                },
+                Provider::None,
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(transferred.load(Ordering::SeqCst) < compressed.len() as u64);
+        assert!(!install.path.join("Data/A.bin").exists());
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn copy_without_usable_url_falls_back_to_raw_file() {
+        let mirror = TempDir::new("test_compression_no_url_mirror");
+        let install = TempDir::new("test_compression_no_url_install");
+        let compressed = zstd::encode_all(contents().as_slice(), 3).unwrap();
+
+        for url in [None, Some("unsupported://mirror/A.bin.compressed")] {
+            let _ = std::fs::remove_file(install.path.join("Data/A.bin"));
+            let mut manifest = manifest(&mirror, "zstd", &compressed);
+            manifest.files[0].compressed.as_mut().unwrap().urls = url
+                .map(|url| (Provider::None, url.to_string()))
+                .into_iter()
+                .collect();
+            let transaction = Transaction::new(manifest, install.path.clone());
+            transaction
+                .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+                .await
+                .unwrap();
+            assert_eq!(
+                std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+                contents()
+            );
+        }
+    }
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{file, file_with, hash, manifest_with, TempDir, UID};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::policy::{ModifiedPolicy, PolicyRules};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::{CustomMode, TransactionOptions};
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+fn manifest() -> Manifest {
+    let custom =
+        |path: &str, url: &str| file_with(path, b"patch data", url, json!({ "Custom": true }));
+    manifest_with(&[
+        file("Data/common.MPQ", b"patch data", "common.MPQ"),
+        custom("Data/patch-A.MPQ", "patch-A.MPQ"),
+        custom("Data/patch-B.MPQ", "patch-B.MPQ"),
+    ])
+}
+
+fn transaction(temp: &TempDir, custom: CustomMode) -> Transaction {
+    Transaction::with_options(
+        manifest(),
+        temp.path.clone(),
+        TransactionOptions {
+            custom,
+            ..Default::default()
+        },
+    )
+}
+
+/// Options to remove custom content that was installed with the manifest hash
+fn remove_installed(policy: ModifiedPolicy) -> TransactionOptions {
+    let mut modified_policy = PolicyRules::default();
+    modified_policy.default = policy;
+    TransactionOptions {
+        custom: CustomMode::Remove,
+        state: Some(InstallState {
+            uid: UID.to_string(),
+            version: "1.0".to_string(),
+            source: None,
+            provider: Some(Provider::None),
+            channel: None,
+            applied_at: 0,
+            files: BTreeMap::from([
+                ("Data/patch-A.MPQ".to_string(), hash(b"patch data")),
+                ("Data/patch-B.MPQ".to_string(), hash(b"patch data")),
+            ]),
+            groups: Default::default(),
+        }),
+        quick_verify: false,
+        modified_policy,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_marks_custom_files() {
+        let temp = TempDir::new("test_custom_report");
+        let report = transaction(&temp, CustomMode::Install).generate_report();
+        let custom: Vec<_> = report
+            .missing_files
+            .iter()
+            .filter(|file| file.custom)
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(custom, vec!["Data/patch-A.MPQ", "Data/patch-B.MPQ"]);
+    }
+
+    #[test]
+    fn skip_and_only_custom() {
+        let temp = TempDir::new("test_custom_modes");
+
+        let skip = transaction(&temp, CustomMode::Skip);
+        assert_eq!(skip.pending_count(), 1);
+        assert_eq!(skip.generate_report().ignored_files.len(), 2);
+
+        let only = transaction(&temp, CustomMode::Only);
+        assert_eq!(only.pending_count(), 2);
+        let ignored = only.generate_report().ignored_files;
+        assert_eq!(ignored.len(), 1);
+        assert_eq!(ignored[0].path, "Data/common.MPQ");
+    }
+
+    #[tokio::test]
+    async fn remove_deletes_installed_custom_files() {
+        let temp = TempDir::new("test_custom_remove");
+        temp.write("Data/common.MPQ", b"patch data");
+        temp.write("Data/patch-A.MPQ", b"custom content");
+
+        let transaction = transaction(&temp, CustomMode::Remove);
+        assert_eq!(transaction.pending_count(), 0);
+        assert_eq!(transaction.removal_count(), 1);
+        assert!(transaction.has_pending_operations());
+
+        let report = transaction.generate_report();
+        assert_eq!(report.removed_files.len(), 1);
+        assert_eq!(report.removed_files[0].path, "Data/patch-A.MPQ");
+        assert_eq!(report.disk_space_change, -14);
+
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert!(!temp.path.join("Data/patch-A.MPQ").exists());
+        assert!(temp.path.join("Data/common.MPQ").exists());
+
+        let files = transaction.install_state(&Provider::None).files;
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["Data/common.MPQ"]);
+    }
+
+    #[tokio::test]
+    async fn remove_applies_modified_policies() {
+        let temp = TempDir::new("test_custom_remove_modified");
+        temp.write("Data/common.MPQ", b"patch data");
+        temp.write("Data/patch-A.MPQ", b"user edits");
+        temp.write("Data/patch-B.MPQ", b"patch data");
+
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            remove_installed(ModifiedPolicy::Backup),
+        );
+        let report = transaction.generate_report();
+        assert_eq!(report.modified_files.len(), 1);
+        assert!(report.modified_files[0].removed);
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert!(!temp.path.join("Data/patch-A.MPQ").exists());
+        assert!(!temp.path.join("Data/patch-B.MPQ").exists());
+        let backups: Vec<_> = std::fs::read_dir(temp.path.join(".patcher/backups"))
+            .unwrap()
+            .collect();
+        assert_eq!(backups.len(), 1);
+        let backup = backups[0].as_ref().unwrap().path();
+        assert_eq!(
+            std::fs::read(backup.join("Data/patch-A.MPQ")).unwrap(),
+            b"user edits"
+        );
+        // Unmodified files are removed without a backup
+        assert!(!backup.join("Data/patch-B.MPQ").exists());
+
+        // With the keep policy the user's version stays installed
+        temp.write("Data/patch-A.MPQ", b"user edits");
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            remove_installed(ModifiedPolicy::Keep),
+        );
+        assert!(!transaction.has_pending_operations());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(temp.path.join("Data/patch-A.MPQ")).unwrap(),
+            b"user edits"
+        );
+    }
+}
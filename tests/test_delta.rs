@@ -0,0 +1,124 @@
+mod common;
+
+use common::{file_with, hash, manifest_with, url, TempDir};
+use rs_manifest_patcher::delta;
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+const OLD: &[u8] = b"patch data version 1, patch data version 1, patch data version 1";
+const NEW: &[u8] = b"patch data version 2, patch data version 1, patch data version 1";
+
+/// Manifest for `NEW` with a delta from `OLD` served from `mirror`
+fn manifest(mirror: &TempDir, delta: &[u8]) -> Manifest {
+    let full = mirror.write("A.bin", NEW);
+    let patch = mirror.write("A.bin.delta", delta);
+    manifest_with(&[file_with(
+        "Data/A.bin",
+        NEW,
+        &url(&full),
+        json!({
+            "Deltas": [{
+                "SourceHash": hash(OLD),
+                "Size": delta.len(),
+                "Urls": { "none": url(&patch) },
+            }],
+        }),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_delta_for_matching_source() {
+        let mirror = TempDir::new("test_delta_plan_mirror");
+        let install = TempDir::new("test_delta_plan_install");
+        let patch = delta::create(OLD, NEW).unwrap();
+        install.write("Data/A.bin", OLD);
+
+        let report =
+            Transaction::new(manifest(&mirror, &patch), install.path.clone()).generate_report();
+        assert_eq!(
+            report.outdated_files[0].delta_size,
+            Some(patch.len() as i64)
+        );
+        assert_eq!(report.total_download_size, patch.len() as u64);
+
+        // Another local version has no delta
+        install.write("Data/A.bin", b"something else");
+        let report =
+            Transaction::new(manifest(&mirror, &patch), install.path.clone()).generate_report();
+        assert_eq!(report.outdated_files[0].delta_size, None);
+        assert_eq!(report.total_download_size, NEW.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn applies_delta() {
+        let mirror = TempDir::new("test_delta_apply_mirror");
+        let install = TempDir::new("test_delta_apply_install");
+        install.write("Data/A.bin", OLD);
+
+        let transaction = Transaction::new(
+            manifest(&mirror, &delta::create(OLD, NEW).unwrap()),
+            install.path.clone(),
+        );
+        // The full file is not needed
+        std::fs::remove_file(mirror.path.join("A.bin")).unwrap();
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), NEW);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_full_download() {
+        let mirror = TempDir::new("test_delta_fallback_mirror");
+        let install = TempDir::new("test_delta_fallback_install");
+        install.write("Data/A.bin", OLD);
+
+        // A delta producing the wrong contents is rejected
+        let wrong = delta::create(OLD, b"not the new version").unwrap();
+        let transaction = Transaction::new(manifest(&mirror, &wrong), install.path.clone());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), NEW);
+
+        // So is a delta that is not a zstd frame at all
+        install.write("Data/A.bin", OLD);
+        let transaction = Transaction::new(manifest(&mirror, b"garbage"), install.path.clone());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), NEW);
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn delta_without_usable_url_falls_back_to_full_download() {
+        let mirror = TempDir::new("test_delta_no_url_mirror");
+        let install = TempDir::new("test_delta_no_url_install");
+        let patch = delta::create(OLD, NEW).unwrap();
+
+        for url in [None, Some("unsupported://mirror/A.bin.delta")] {
+            install.write("Data/A.bin", OLD);
+            let mut manifest = manifest(&mirror, &patch);
+            manifest.files[0].deltas[0].urls = url
+                .map(|url| (Provider::None, url.to_string()))
+                .into_iter()
+                .collect();
+            let transaction = Transaction::new(manifest, install.path.clone());
+            transaction
+                .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(install.path.join("Data/A.bin")).unwrap(), NEW);
+        }
+    }
+}
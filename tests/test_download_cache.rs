@@ -0,0 +1,188 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use common::{file, hash, manifest_with, url, TempDir};
+use rs_manifest_patcher::download_cache::DownloadCache;
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+fn manifest(contents: &[u8], url: &str) -> Manifest {
+    manifest_with(&[file("Data/A.bin", contents, url)])
+}
+
+fn options(cache: &DownloadCache) -> TransactionOptions {
+    TransactionOptions {
+        cache: Some(cache.clone()),
+        ..Default::default()
+    }
+}
+
+/// Add an entry last used `age` ago
+fn insert(cache: &DownloadCache, source: &TempDir, contents: &[u8], age: u64) {
+    let path = source.write("entry.bin", contents);
+    cache.insert(&hash(contents), &path).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(cache.path(&hash(contents)))
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(age))
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_least_recently_used_entries() {
+        let dir = TempDir::new("test_download_cache_prune");
+        let source = TempDir::new("test_download_cache_prune_source");
+        let cache = DownloadCache::new(dir.path.clone(), 20);
+        insert(&cache, &source, b"oldest....", 300);
+        insert(&cache, &source, b"newest....", 100);
+        insert(&cache, &source, b"middle....", 200);
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].hash, hash(b"oldest...."));
+
+        let removed = cache.prune().unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].hash, hash(b"oldest...."));
+        assert!(cache.contains(&hash(b"middle....")));
+        assert!(cache.contains(&hash(b"newest....")));
+    }
+
+    #[test]
+    fn verify_removes_corrupt_entries() {
+        let dir = TempDir::new("test_download_cache_verify");
+        let source = TempDir::new("test_download_cache_verify_source");
+        let cache = DownloadCache::new(dir.path.clone(), u64::MAX);
+        insert(&cache, &source, b"patch data", 0);
+        insert(&cache, &source, b"other data", 0);
+        std::fs::write(cache.path(&hash(b"other data")), b"corrupted!").unwrap();
+        // Files the user or another tool left in the cache are not entries
+        dir.write("ab/notes", b"not a cache entry");
+        dir.write("ab/README", b"not a cache entry");
+
+        let corrupt = cache.verify().unwrap();
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].hash, hash(b"other data"));
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        assert_eq!(cache.purge().unwrap().len(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn second_installation_uses_cache() {
+        let mirror = TempDir::new("test_download_cache_shared_mirror");
+        let first = TempDir::new("test_download_cache_shared_first");
+        let second = TempDir::new("test_download_cache_shared_second");
+        let dir = TempDir::new("test_download_cache_shared");
+        let cache = DownloadCache::new(dir.path.clone(), u64::MAX);
+        let source = mirror.write("A.bin", b"patch data");
+
+        let transaction = Transaction::with_options(
+            manifest(b"patch data", &url(&source)),
+            first.path.clone(),
+            options(&cache),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert!(cache.contains(&hash(b"patch data")));
+
+        // The second installation does not need the mirror
+        std::fs::remove_file(&source).unwrap();
+        let transaction = Transaction::with_options(
+            manifest(b"patch data", &url(&source)),
+            second.path.clone(),
+            options(&cache),
+        );
+        let report = transaction.generate_report();
+        assert!(report.missing_files[0].cached);
+        assert_eq!(report.total_download_size, 0);
+        assert_eq!(report.total_cached_size, 10);
+
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(second.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[tokio::test]
+    async fn corrupt_entry_falls_back_to_download() {
+        let mirror = TempDir::new("test_download_cache_corrupt_mirror");
+        let install = TempDir::new("test_download_cache_corrupt_install");
+        let dir = TempDir::new("test_download_cache_corrupt");
+        let source = TempDir::new("test_download_cache_corrupt_source");
+        let cache = DownloadCache::new(dir.path.clone(), u64::MAX);
+        insert(&cache, &source, b"patch data", 0);
+        std::fs::write(cache.path(&hash(b"patch data")), b"corrupted!").unwrap();
+
+        let transaction = Transaction::with_options(
+            manifest(b"patch data", &url(&mirror.write("A.bin", b"patch data"))),
+            install.path.clone(),
+            options(&cache),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+        // The corrupt entry was replaced by the downloaded file
+        assert!(cache.verify().unwrap().is_empty());
+        assert!(cache.contains(&hash(b"patch data")));
+    }
+
+    #[tokio::test]
+    async fn downloads_land_in_the_cache_first() {
+        let mirror = TempDir::new("test_download_cache_first_mirror");
+        let install = TempDir::new("test_download_cache_first_install");
+        let dir = TempDir::new("test_download_cache_first");
+        let cache = DownloadCache::new(dir.path.clone(), u64::MAX);
+        install.write("Data/A.bin", b"older data");
+        let source = mirror.write("A.bin", b"corrupted!");
+
+        // A corrupt download leaves neither an entry nor a partial file behind
+        let transaction = Transaction::with_options(
+            manifest(b"patch data", &url(&source)),
+            install.path.clone(),
+            options(&cache),
+        );
+        assert!(transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .is_err());
+        let prefix = dir.path.join(&hash(b"patch data")[..2]);
+        assert_eq!(std::fs::read_dir(&prefix).unwrap().count(), 0);
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            b"older data"
+        );
+
+        std::fs::write(&source, b"patch data").unwrap();
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_dir(&prefix).unwrap().count(), 1);
+        assert!(cache.verify().unwrap().is_empty());
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+        assert!(!install.path.join("Data/A.bin.partial").exists());
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::TempDir;
+use rs_manifest_patcher::game::{
+    check_base_path, detect_locale, is_game_dir, parse_locale, verify_game_integrity,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_game_dir_by_client_files() {
+        let temp = TempDir::new("test_game_dir_detect");
+        assert!(!is_game_dir(&temp.path));
+
+        temp.write("Data/lichking.MPQ", b"");
+        assert!(is_game_dir(&temp.path));
+    }
+
+    #[test]
+    fn base_path_must_exist() {
+        let result = check_base_path(std::path::Path::new("/non/existent/path"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn base_path_must_be_directory() {
+        let temp = TempDir::new("test_game_dir_not_dir");
+        let file = temp.write("file.txt", b"");
+        assert!(check_base_path(&file).is_err());
+    }
+
+    #[test]
+    fn writable_base_path_is_accepted() {
+        let temp = TempDir::new("test_game_dir_writable");
+        assert!(check_base_path(&temp.path).is_ok());
+        // The write probe must not be left behind
+        assert_eq!(std::fs::read_dir(&temp.path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn leftover_write_probe_is_reused() {
+        let temp = TempDir::new("test_game_dir_leftover_probe");
+        temp.write(".rs_manifest_patcher_write_check", b"interrupted");
+        assert!(check_base_path(&temp.path).is_ok());
+        assert_eq!(std::fs::read_dir(&temp.path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn detects_locale_from_data_dirs() {
+        let temp = TempDir::new("test_game_dir_locale");
+        assert_eq!(detect_locale(&temp.path), None);
+
+        // A directory holding its locale MPQ wins over an empty one
+        temp.write("Data/deDE/readme.txt", b"");
+        assert_eq!(detect_locale(&temp.path), Some("deDE"));
+        temp.write("Data/enGB/locale-enGB.MPQ", b"");
+        assert_eq!(detect_locale(&temp.path), Some("enGB"));
+    }
+
+    #[test]
+    fn config_locale_takes_precedence() {
+        let temp = TempDir::new("test_game_dir_locale_config");
+        temp.write("Data/enUS/locale-enUS.MPQ", b"");
+        temp.write(
+            "WTF/Config.wtf",
+            b"SET gxResolution \"1920x1080\"\nSET locale \"frfr\"\n",
+        );
+        assert_eq!(detect_locale(&temp.path), Some("frFR"));
+    }
+
+    #[test]
+    fn parses_known_locales() {
+        assert_eq!(parse_locale("ENUS"), Some("enUS"));
+        assert_eq!(parse_locale("xxXX"), None);
+    }
+
+    #[test]
+    fn integrity_requires_the_locale_archive() {
+        let temp = TempDir::new("test_game_dir_integrity");
+        temp.write("Battle.net.dll", b"");
+        temp.write("Data/lichking.MPQ", b"");
+        temp.write("Data/patch-3.MPQ", b"");
+        temp.write("Data/enUS/readme.txt", b"");
+        assert!(!verify_game_integrity(&temp.path).unwrap());
+
+        temp.write("Data/enUS/locale-enUS.MPQ", b"");
+        assert!(verify_game_integrity(&temp.path).unwrap());
+    }
+}
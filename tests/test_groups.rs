@@ -0,0 +1,162 @@
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use common::{file, file_with, hash, manifest_with, TempDir, UID};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::policy::{ModifiedPolicy, PolicyRules};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+fn manifest() -> Manifest {
+    manifest_with(&[
+        file("Data/common.MPQ", b"patch data", "common.MPQ"),
+        file_with(
+            "Data/patch-hd.MPQ",
+            b"patch data",
+            "patch-hd.MPQ",
+            json!({ "Custom": true, "Group": "hd-textures", "Optional": true }),
+        ),
+        file_with(
+            "Data/patch-music.MPQ",
+            b"patch data",
+            "patch-music.MPQ",
+            json!({ "Custom": true, "Group": "music", "Optional": true }),
+        ),
+    ])
+}
+
+fn options(groups: &[&str], installed: &[&str]) -> TransactionOptions {
+    TransactionOptions {
+        state: Some(InstallState {
+            uid: UID.to_string(),
+            version: "1.0".to_string(),
+            source: None,
+            provider: None,
+            channel: None,
+            applied_at: 0,
+            files: installed
+                .iter()
+                .map(|path| (path.to_string(), hash(b"patch data")))
+                .collect::<BTreeMap<_, _>>(),
+            groups: BTreeSet::new(),
+        }),
+        groups: groups.iter().map(|group| group.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_optional_files() {
+        let manifest = manifest();
+        assert_eq!(manifest.files[0].optional_group(), None);
+        assert_eq!(manifest.files[1].group.as_deref(), Some("hd-textures"));
+        assert_eq!(manifest.files[1].optional_group(), Some("hd-textures"));
+
+        let ungrouped = manifest_with(&[file_with(
+            "Data/extra.MPQ",
+            b"patch data",
+            "",
+            json!({ "Custom": true, "Optional": true, "Urls": {} }),
+        )]);
+        assert_eq!(ungrouped.files[0].optional_group(), Some("optional"));
+    }
+
+    #[test]
+    fn plans_selected_groups_only() {
+        let temp = TempDir::new("test_groups_select");
+        let transaction =
+            Transaction::with_options(manifest(), temp.path.clone(), options(&["music"], &[]));
+
+        let report = transaction.generate_report();
+        let missing: Vec<_> = report
+            .missing_files
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect();
+        assert_eq!(missing, vec!["Data/common.MPQ", "Data/patch-music.MPQ"]);
+
+        let groups: Vec<_> = report
+            .optional_groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.selected, group.files))
+            .collect();
+        assert_eq!(groups, vec![("hd-textures", false, 1), ("music", true, 1)]);
+
+        let state = transaction.install_state(&Provider::None);
+        assert_eq!(state.groups, BTreeSet::from(["music".to_string()]));
+        assert!(!state.files.contains_key("Data/patch-hd.MPQ"));
+    }
+
+    #[test]
+    fn removes_installed_files_of_deselected_groups() {
+        let temp = TempDir::new("test_groups_deselect");
+        temp.write("Data/patch-hd.MPQ", b"patch data");
+        temp.write("Data/patch-music.MPQ", b"patch data");
+
+        // Only files the patcher installed are removed
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            options(&[], &["Data/patch-hd.MPQ"]),
+        );
+        let removed = transaction.generate_report().removed_files;
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].path, "Data/patch-hd.MPQ");
+        assert_eq!(transaction.removal_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn deselected_files_follow_the_modified_policy() {
+        let temp = TempDir::new("test_groups_deselect_modified");
+        temp.write("Data/common.MPQ", b"patch data");
+        temp.write("Data/patch-hd.MPQ", b"user edits");
+
+        let with_policy = |policy| {
+            let mut modified_policy = PolicyRules::default();
+            modified_policy.default = policy;
+            TransactionOptions {
+                modified_policy,
+                ..options(&[], &["Data/patch-hd.MPQ"])
+            }
+        };
+
+        // Kept files stay installed and recorded
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            with_policy(ModifiedPolicy::Keep),
+        );
+        assert_eq!(transaction.removal_count(), 0);
+        let state = transaction.install_state(&Provider::None);
+        assert!(state.files.contains_key("Data/patch-hd.MPQ"));
+
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            with_policy(ModifiedPolicy::Backup),
+        );
+        assert_eq!(transaction.removal_count(), 1);
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert!(!temp.path.join("Data/patch-hd.MPQ").exists());
+        let backup = std::fs::read_dir(temp.path.join(".patcher/backups"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        assert_eq!(
+            std::fs::read(backup.join("Data/patch-hd.MPQ")).unwrap(),
+            b"user edits"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{Response, TestServer};
+use rs_manifest_patcher::http::{build_client, parse_header, HttpConfig, USER_AGENT};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header_argument() {
+        assert_eq!(
+            parse_header("X-Api-Key: secret"),
+            Ok(("X-Api-Key".to_string(), "secret".to_string()))
+        );
+        assert!(parse_header("no-colon").is_err());
+        assert!(parse_header(": value").is_err());
+    }
+
+    #[tokio::test]
+    async fn client_sends_user_agent_and_headers() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_by_server = seen.clone();
+        let server = TestServer::start(move |request| {
+            seen_by_server.lock().unwrap().push(request.headers.clone());
+            Response::ok(b"ok")
+        })
+        .await;
+
+        let client = build_client(&HttpConfig {
+            headers: vec![("X-Api-Key".to_string(), "secret".to_string())],
+            bearer_token: Some("token".to_string()),
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        client.get(server.url("/")).send().await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        let headers = &seen[0];
+        assert_eq!(headers.get("user-agent").unwrap(), USER_AGENT);
+        assert_eq!(headers.get("x-api-key").unwrap(), "secret");
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer token");
+    }
+
+    #[tokio::test]
+    async fn client_applies_read_timeout() {
+        let server = TestServer::start(|_| Response {
+            delay: Duration::from_millis(500),
+            ..Response::ok(b"late")
+        })
+        .await;
+
+        let client = build_client(&HttpConfig {
+            read_timeout: Some(Duration::from_millis(50)),
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        let result = client.get(server.url("/")).send().await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_proxy_is_rejected() {
+        let result = build_client(&HttpConfig {
+            proxy: Some("not a proxy url".to_string()),
+            ..HttpConfig::default()
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn missing_ca_certificate_is_rejected() {
+        let result = build_client(&HttpConfig {
+            ca_certificates: vec!["/non/existent/ca.pem".into()],
+            ..HttpConfig::default()
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{file, manifest_with, TempDir, UID};
+use rs_manifest_patcher::ignore::IgnoreRules;
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+fn manifest() -> Manifest {
+    manifest_with(&[
+        file("Wow.exe", b"patch data", "Wow.exe"),
+        file("Data/patch-A.MPQ", b"patch data", "patch-A.MPQ"),
+        file("Data/patch-B.MPQ", b"patch data", "patch-B.MPQ"),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let rules = IgnoreRules::parse(
+            "# comments and blank lines are skipped\n\n*.MPQ\n!patch-B.MPQ\nInterface/\n",
+        );
+        assert!(rules.is_ignored("Data/patch-A.MPQ"));
+        assert!(!rules.is_ignored("Data/patch-B.MPQ"));
+        assert!(rules.is_ignored("Interface/AddOns/Foo.toc"));
+        assert!(!rules.is_ignored("Interface"));
+        assert!(!rules.is_ignored("Wow.exe"));
+    }
+
+    #[test]
+    fn command_line_rules_take_precedence() {
+        let mut rules = IgnoreRules::parse("Data/\n");
+        rules.extend({
+            let mut cli = IgnoreRules::default();
+            cli.include("patch-A.MPQ").unwrap();
+            cli
+        });
+        assert!(!rules.is_ignored("Data/patch-A.MPQ"));
+        assert!(rules.is_ignored("Data/patch-B.MPQ"));
+        assert!(rules.exclude("").is_err());
+    }
+
+    #[test]
+    fn new_applies_ignore_file() {
+        let temp = TempDir::new("test_ignore_file");
+        temp.write(".patcherignore", b"Wow.exe\n");
+        temp.write("Wow.exe", b"locally patched");
+
+        let transaction = Transaction::new(manifest(), temp.path.clone());
+        assert_eq!(transaction.pending_count(), 2);
+
+        let report = transaction.generate_report();
+        assert_eq!(report.ignored_files.len(), 1);
+        assert_eq!(report.ignored_files[0].path, "Wow.exe");
+        assert_eq!(report.ignored_files[0].current_size, Some(15));
+        assert!(report
+            .missing_files
+            .iter()
+            .all(|file| file.path != "Wow.exe"));
+    }
+
+    #[test]
+    fn ignored_files_keep_recorded_state() {
+        let temp = TempDir::new("test_ignore_state");
+        let mut ignore = IgnoreRules::default();
+        ignore.exclude("Data").unwrap();
+        let state = InstallState {
+            uid: UID.to_string(),
+            version: "0.9".to_string(),
+            source: None,
+            provider: None,
+            channel: None,
+            applied_at: 0,
+            files: BTreeMap::from([(
+                "Data/patch-A.MPQ".to_string(),
+                "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            )]),
+            groups: Default::default(),
+        };
+
+        let transaction = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            TransactionOptions {
+                state: Some(state),
+                ignore,
+                ..Default::default()
+            },
+        );
+        assert_eq!(transaction.pending_count(), 1);
+
+        let files = transaction.install_state(&Provider::None).files;
+        assert_eq!(
+            files.get("Data/patch-A.MPQ").map(String::as_str),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        assert!(!files.contains_key("Data/patch-B.MPQ"));
+        assert!(files.contains_key("Wow.exe"));
+    }
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{file_with, manifest_with, TempDir};
+use rs_manifest_patcher::manifest::Manifest;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+
+fn manifest() -> Manifest {
+    manifest_with(&[
+        file_with(
+            "Data/patch-A.MPQ",
+            b"patch data",
+            "patch-A.MPQ",
+            json!({ "Custom": true }),
+        ),
+        file_with(
+            "Data/enUS/patch-enUS-A.MPQ",
+            b"patch data",
+            "patch-enUS-A.MPQ",
+            json!({ "Custom": true, "Locale": "enUS" }),
+        ),
+        file_with(
+            "Data/deDE/patch-deDE-A.MPQ",
+            b"patch data",
+            "patch-deDE-A.MPQ",
+            json!({ "Custom": true, "Locale": "deDE" }),
+        ),
+    ])
+}
+
+fn planned(locale: Option<&str>) -> Vec<String> {
+    let temp = TempDir::new(&format!("test_locale_{}", locale.unwrap_or("none")));
+    let transaction = Transaction::with_options(
+        manifest(),
+        temp.path.clone(),
+        TransactionOptions {
+            locale: locale.map(String::from),
+            ..Default::default()
+        },
+    );
+    transaction
+        .generate_report()
+        .missing_files
+        .into_iter()
+        .map(|file| file.path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_manifest_locales() {
+        assert_eq!(manifest().locales(), vec!["deDE", "enUS"]);
+    }
+
+    #[test]
+    fn plans_matching_locale_only() {
+        assert_eq!(
+            planned(Some("enus")),
+            vec!["Data/patch-A.MPQ", "Data/enUS/patch-enUS-A.MPQ"]
+        );
+        assert_eq!(
+            planned(Some("deDE")),
+            vec!["Data/patch-A.MPQ", "Data/deDE/patch-deDE-A.MPQ"]
+        );
+    }
+
+    #[test]
+    fn unknown_locale_skips_locale_files() {
+        assert_eq!(planned(None), vec!["Data/patch-A.MPQ"]);
+        assert_eq!(planned(Some("frFR")), vec!["Data/patch-A.MPQ"]);
+    }
+}
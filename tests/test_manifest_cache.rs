@@ -0,0 +1,98 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use common::{Response, TempDir, TestServer};
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest};
+use rs_manifest_patcher::manifest_cache::ManifestCache;
+use rs_manifest_patcher::signature::TrustedKeys;
+
+const MANIFEST_JSON: &str = r#"
+{
+    "Version": "1.0",
+    "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+    "Files": []
+}
+"#;
+
+/// Serve the manifest with an ETag and answer matching conditional requests with 304
+async fn start_manifest_server(not_modified: Arc<AtomicUsize>) -> TestServer {
+    TestServer::start(move |request| {
+        if request.headers.get("if-none-match").map(String::as_str) == Some("\"v1\"") {
+            not_modified.fetch_add(1, Ordering::SeqCst);
+            return Response {
+                status: 304,
+                ..Response::ok(b"")
+            };
+        }
+        Response {
+            headers: vec![("ETag".to_string(), "\"v1\"".to_string())],
+            ..Response::ok(MANIFEST_JSON.as_bytes())
+        }
+    })
+    .await
+}
+
+/// Options with an offline cache, accepting the unsigned test manifest
+fn cached_options(temp: &TempDir) -> BuildOptions {
+    let mut trust = TrustedKeys::default();
+    trust.allow_unsigned = true;
+    BuildOptions {
+        cache_path: Some(temp.path.join("manifest-cache.json")),
+        trust,
+        ..BuildOptions::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn revalidates_with_etag() {
+        let temp = TempDir::new("test_manifest_cache_etag");
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let server = start_manifest_server(not_modified.clone()).await;
+
+        let location = Location::parse(server.url("/manifest.json")).unwrap();
+        let options = cached_options(&temp);
+
+        let first = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(first.uid, "5a63cd8c-956c-48a0-95ae-7e41d1e73182");
+        let cache = ManifestCache::load(options.cache_path.as_ref().unwrap());
+        let cached = cache.entry(&location.to_string()).unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"v1\""));
+
+        let second = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(not_modified.load(Ordering::SeqCst), 1);
+        assert_eq!(second.uid, first.uid);
+        assert!(second.origin.stale_since.is_none());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_cache_when_offline() {
+        let temp = TempDir::new("test_manifest_cache_offline");
+        let server = start_manifest_server(Arc::new(AtomicUsize::new(0))).await;
+        let location = Location::parse(server.url("/manifest.json")).unwrap();
+        let options = cached_options(&temp);
+
+        Manifest::build(&location, &options).await.unwrap();
+        drop(server);
+
+        let manifest = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(manifest.version, "1.0");
+        assert!(manifest.origin.stale_since.is_some());
+    }
+
+    #[tokio::test]
+    async fn offline_without_cache_fails() {
+        let temp = TempDir::new("test_manifest_cache_empty");
+        let server = start_manifest_server(Arc::new(AtomicUsize::new(0))).await;
+        let location = Location::parse(server.url("/manifest.json")).unwrap();
+        drop(server);
+
+        let options = cached_options(&temp);
+        assert!(Manifest::build(&location, &options).await.is_err());
+    }
+}
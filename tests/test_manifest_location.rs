@@ -37,4 +37,24 @@ mod tests {
         let result = Location::parse("C://non//existent//file.txt".to_string());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn valid_stdin() {
+        let result = Location::parse("-".to_string());
+        assert!(matches!(result, Ok(Location::Stdin)));
+    }
+
+    #[test]
+    fn valid_file_url() {
+        let path = std::env::current_dir().unwrap().join("manifest.json");
+        let url = url::Url::from_file_path(&path).unwrap();
+        let result = Location::parse(url.to_string());
+        assert!(matches!(result, Ok(Location::FilePath(p)) if p == path));
+    }
+
+    #[test]
+    fn invalid_file_url() {
+        let result = Location::parse("file:///non/existent/manifest.json".to_string());
+        assert!(result.is_err());
+    }
 }
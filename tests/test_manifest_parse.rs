@@ -1,7 +1,18 @@
 mod common;
 
 use common::TempFile;
-use rs_manifest_patcher::manifest::{Location, Manifest};
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest, Provider};
+use rs_manifest_patcher::signature::TrustedKeys;
+
+/// The manifests in these tests are not signed
+fn unsigned_options() -> BuildOptions {
+    let mut trust = TrustedKeys::default();
+    trust.allow_unsigned = true;
+    BuildOptions {
+        trust,
+        ..BuildOptions::default()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -34,7 +45,7 @@ mod tests {
 
         // Deserialize manifest from the file
         let location = Location::FilePath(temp_file.path.clone());
-        let manifest = Manifest::build(&location)
+        let manifest = Manifest::build(&location, &unsigned_options())
             .await
             .expect("Failed to build manifest");
         assert_eq!(manifest.version, "1.0");
@@ -51,7 +62,105 @@ mod tests {
 
         // Expect Manifest::build to error out on invalid JSON
         let location = Location::FilePath(temp_file.path.clone());
-        let result = Manifest::build(&location).await;
+        let result = Manifest::build(&location, &unsigned_options()).await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_manifest_providers() {
+        let json_content = r#"
+        {
+            "Version": "1.0",
+            "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+            "Files": [
+                {
+                    "Path": "files/A.bin",
+                    "Hash": "b6d81b360a5672d80c27430f39153e2c",
+                    "Size": 1048576,
+                    "Custom": true,
+                    "Urls": {
+                        "cloudflare": "http://localhost:8080/files/A.bin",
+                        "mirror-eu": "http://localhost:8080/files/A.bin"
+                    }
+                },
+                {
+                    "Path": "files/B.bin",
+                    "Hash": "b6d81b360a5672d80c27430f39153e2c",
+                    "Size": 1048576,
+                    "Custom": true,
+                    "Urls": {
+                        "cloudflare": "http://localhost:8080/files/B.bin"
+                    }
+                }
+            ]
+        }
+        "#;
+        let manifest = Manifest::from_json(json_content).unwrap();
+
+        assert_eq!(
+            manifest.providers(),
+            vec![
+                Provider::Cloudflare,
+                Provider::Other("mirror-eu".to_string())
+            ]
+        );
+        assert!(manifest
+            .validate_provider(&Provider::Other("mirror-eu".to_string()))
+            .is_ok());
+        assert!(manifest.validate_provider(&Provider::DigitalOcean).is_err());
+
+        let missing = manifest.files_without_provider(&Provider::Other("mirror-eu".to_string()));
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].path, "files/B.bin");
+    }
+
+    #[test]
+    fn test_manifest_rejects_invalid_hash() {
+        for hash in ["../../../etc/passwd", "b6d81b360a5672d80c27430f39153e2", ""] {
+            let json_content = format!(
+                r#"{{
+                    "Version": "1.0",
+                    "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+                    "Files": [
+                        {{
+                            "Path": "files/A.bin",
+                            "Hash": "{hash}",
+                            "Size": 1,
+                            "Custom": false,
+                            "Urls": {{ "none": "http://localhost:8080/files/A.bin" }}
+                        }}
+                    ]
+                }}"#
+            );
+            assert!(Manifest::from_json(&json_content).is_err(), "{hash}");
+        }
+    }
+
+    #[test]
+    fn test_manifest_rejects_invalid_chunks() {
+        for (offset, size) in [(0, 0), (8, 4), (u64::MAX, 2)] {
+            let json_content = format!(
+                r#"{{
+                    "Version": "1.0",
+                    "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+                    "Files": [
+                        {{
+                            "Path": "files/A.bin",
+                            "Hash": "b6d81b360a5672d80c27430f39153e2c",
+                            "Size": 10,
+                            "Custom": false,
+                            "Urls": {{ "none": "http://localhost:8080/files/A.bin" }},
+                            "Chunks": [
+                                {{ "Offset": {offset}, "Size": {size}, "Hash": "b6d81b360a5672d80c27430f39153e2c" }}
+                            ]
+                        }}
+                    ]
+                }}"#
+            );
+            assert!(
+                Manifest::from_json(&json_content).is_err(),
+                "{offset}+{size}"
+            );
+        }
+    }
 }
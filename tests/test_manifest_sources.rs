@@ -0,0 +1,239 @@
+mod common;
+
+use common::{Response, TempDir, TestServer};
+use rs_manifest_patcher::channel::Channel;
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest};
+use rs_manifest_patcher::signature::TrustedKeys;
+
+const UID: &str = "5a63cd8c-956c-48a0-95ae-7e41d1e73182";
+
+fn manifest_json(version: &str) -> String {
+    manifest_json_with_uid(version, UID)
+}
+
+fn manifest_json_with_uid(version: &str, uid: &str) -> String {
+    format!(r#"{{ "Version": "{version}", "Uid": "{uid}", "Files": [] }}"#)
+}
+
+fn options(temp: &TempDir) -> BuildOptions {
+    let mut trust = TrustedKeys::default();
+    trust.allow_unsigned = true;
+    BuildOptions {
+        cache_path: Some(temp.path.join("manifest-cache.json")),
+        trust,
+        ..BuildOptions::default()
+    }
+}
+
+async fn start_server() -> TestServer {
+    TestServer::start(|request| match request.path.as_str() {
+        "/new.json" => Response::ok(manifest_json("2.0").as_bytes()),
+        "/other.json" => Response::ok(
+            manifest_json_with_uid("2.0", "0d4bc9a4-5a4f-4b8e-9b1e-3c0f6a1f2d7e").as_bytes(),
+        ),
+        "/broken.json" => Response::ok(b"invalid json"),
+        "/ptr.json" => Response::ok(manifest_json("2.0").as_bytes()),
+        "/production.json" => Response::ok(manifest_json("1.0").as_bytes()),
+        _ => Response::not_found(),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn falls_back_to_next_source() {
+        let temp = TempDir::new("test_sources_fallback");
+        let server = start_server().await;
+        let file = temp.write("manifest.json", manifest_json("1.5").as_bytes());
+
+        let locations = vec![
+            Location::parse(server.url("/missing.json")).unwrap(),
+            Location::parse(server.url("/broken.json")).unwrap(),
+            Location::FilePath(file.clone()),
+        ];
+        let manifest = Manifest::build_any(&locations, &options(&temp))
+            .await
+            .unwrap();
+
+        assert_eq!(manifest.version, "1.5");
+        assert_eq!(
+            manifest.origin.location.unwrap().to_string(),
+            file.display().to_string()
+        );
+        assert_eq!(manifest.origin.skipped.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_source_works() {
+        let temp = TempDir::new("test_sources_none");
+        let server = start_server().await;
+        let locations = vec![
+            Location::parse(server.url("/missing.json")).unwrap(),
+            Location::parse(server.url("/broken.json")).unwrap(),
+        ];
+        assert!(Manifest::build_any(&locations, &options(&temp))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_older_manifest_than_seen() {
+        let temp = TempDir::new("test_sources_rollback");
+        let server = start_server().await;
+        let mirror = temp.write("mirror.json", manifest_json("2.0").as_bytes());
+        let sources = [
+            Location::FilePath(mirror.clone()),
+            Location::parse(server.url("/new.json")).unwrap(),
+        ];
+
+        // Seeing version 2.0 once makes it the baseline for these sources
+        Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap();
+
+        // The first mirror falls behind
+        std::fs::write(&mirror, manifest_json("1.0")).unwrap();
+        let manifest = Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap();
+        assert_eq!(manifest.version, "2.0");
+        assert_eq!(manifest.origin.skipped.len(), 1);
+        assert!(manifest.origin.skipped[0].contains("older"));
+    }
+
+    #[tokio::test]
+    async fn older_mirror_answering_first_is_rejected_on_every_run() {
+        let temp = TempDir::new("test_sources_rollback_runs");
+        let server = start_server().await;
+        let mirror = temp.write("mirror.json", manifest_json("2.0").as_bytes());
+        let sources = [
+            Location::FilePath(mirror.clone()),
+            Location::parse(server.url("/new.json")).unwrap(),
+        ];
+        Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap();
+        std::fs::write(&mirror, manifest_json("1.0")).unwrap();
+
+        // The rejected mirror must not become the baseline for the next run
+        for _ in 0..2 {
+            let manifest = Manifest::build_any(&sources, &options(&temp))
+                .await
+                .unwrap();
+            assert_eq!(manifest.version, "2.0");
+        }
+
+        // A one-off source list has its own baseline and does not move the one of the mirrors
+        Manifest::build_any(&sources[..1], &options(&temp))
+            .await
+            .unwrap();
+        let manifest = Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap();
+        assert_eq!(manifest.version, "2.0");
+    }
+
+    #[tokio::test]
+    async fn rejects_same_version_with_different_uid() {
+        let temp = TempDir::new("test_sources_uid");
+        let server = start_server().await;
+        let mirror = temp.write("mirror.json", manifest_json("2.0").as_bytes());
+        let sources = [
+            Location::FilePath(mirror.clone()),
+            Location::parse(server.url("/missing.json")).unwrap(),
+        ];
+        Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap();
+
+        std::fs::write(
+            &mirror,
+            manifest_json_with_uid("2.0", "0d4bc9a4-5a4f-4b8e-9b1e-3c0f6a1f2d7e"),
+        )
+        .unwrap();
+        let error = Manifest::build_any(&sources, &options(&temp))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("UID"));
+    }
+
+    #[tokio::test]
+    async fn switching_channels_keeps_separate_baselines() {
+        let temp = TempDir::new("test_sources_channels");
+        let server = start_server().await;
+        let template = server.url("/{channel}.json");
+        let ptr = Channel::Ptr.manifest_location(&template).unwrap();
+        let production = Channel::Production.manifest_location(&template).unwrap();
+
+        let manifest = Manifest::build_any(std::slice::from_ref(&ptr), &options(&temp))
+            .await
+            .unwrap();
+        assert_eq!(manifest.version, "2.0");
+
+        // Going back to production is not a rollback of the PTR release, on every run
+        for _ in 0..2 {
+            let manifest = Manifest::build_any(std::slice::from_ref(&production), &options(&temp))
+                .await
+                .unwrap();
+            assert_eq!(manifest.version, "1.0");
+        }
+        Manifest::build_any(&[ptr], &options(&temp)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reset_baseline_accepts_republished_manifest() {
+        let temp = TempDir::new("test_sources_reset");
+        let mirror = temp.write("mirror.json", manifest_json("2.0").as_bytes());
+        let location = Location::FilePath(mirror.clone());
+        let reset = BuildOptions {
+            reset_baseline: true,
+            ..options(&temp)
+        };
+        Manifest::build(&location, &options(&temp)).await.unwrap();
+
+        // Version 2.0 is republished under a new UID
+        let uid = "0d4bc9a4-5a4f-4b8e-9b1e-3c0f6a1f2d7e";
+        std::fs::write(&mirror, manifest_json_with_uid("2.0", uid)).unwrap();
+        assert!(Manifest::build(&location, &options(&temp)).await.is_err());
+        let manifest = Manifest::build(&location, &reset).await.unwrap();
+        assert_eq!(manifest.uid, uid);
+
+        // The republished manifest is the new baseline
+        Manifest::build(&location, &options(&temp)).await.unwrap();
+        std::fs::write(&mirror, manifest_json("1.0")).unwrap();
+        assert!(Manifest::build(&location, &options(&temp)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn prefers_live_source_over_stale_cache() {
+        let temp = TempDir::new("test_sources_stale");
+        let server = start_server().await;
+        let offline = Location::parse(server.url("/new.json")).unwrap();
+        Manifest::build_any(std::slice::from_ref(&offline), &options(&temp))
+            .await
+            .unwrap();
+        drop(server);
+
+        let file = temp.write("manifest.json", manifest_json("2.0").as_bytes());
+        let manifest = Manifest::build_any(
+            &[offline.clone(), Location::FilePath(file.clone())],
+            &options(&temp),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            manifest.origin.location.unwrap().to_string(),
+            file.display().to_string()
+        );
+        assert!(manifest.origin.stale_since.is_none());
+
+        // Only the unreachable source left: the cached copy is used
+        let manifest = Manifest::build_any(&[offline], &options(&temp))
+            .await
+            .unwrap();
+        assert!(manifest.origin.stale_since.is_some());
+    }
+}
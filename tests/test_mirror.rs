@@ -0,0 +1,172 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use common::{file_with, hash, manifest_with, url, TempDir};
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest, Provider};
+use rs_manifest_patcher::mirror;
+use rs_manifest_patcher::signature::{self, SignatureStatus, TrustedKeys};
+use rs_manifest_patcher::transaction::TransactionOptions;
+use serde_json::json;
+use url::Url;
+
+/// Manifest with a stock file that has a delta, and an optional custom file for one locale
+fn manifest(source: &TempDir) -> Manifest {
+    let stock = url(&source.write("A.bin", b"patch data"));
+    let localized = url(&source.write("L.bin", b"loc data"));
+    manifest_with(&[
+        file_with(
+            "Data/A.bin",
+            b"patch data",
+            &stock,
+            json!({
+                "Urls": { "cloudflare": stock, "none": stock },
+                "Deltas": [
+                    { "SourceHash": hash(b"old data"), "Size": 4, "Urls": { "none": "A.delta" } }
+                ],
+            }),
+        ),
+        file_with(
+            "Data/enUS/My File.bin",
+            b"loc data",
+            &localized,
+            json!({
+                "Custom": true,
+                "Optional": true,
+                "Locale": "enUS",
+                "Urls": { "cloudflare": localized },
+            }),
+        ),
+    ])
+}
+
+async fn run_mirror(manifest: &Manifest, dir: &TempDir) {
+    let transaction = mirror::plan(manifest, &dir.path, TransactionOptions::default()).unwrap();
+    transaction
+        .download(&reqwest::Client::new(), |_| Ok(()), Provider::Cloudflare)
+        .await
+        .unwrap();
+    transaction
+        .install_state(&Provider::Cloudflare)
+        .save(&dir.path)
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn downloads_every_file() {
+        let source = TempDir::new("test_mirror_every_source");
+        let dir = TempDir::new("test_mirror_every_dir");
+
+        run_mirror(&manifest(&source), &dir).await;
+
+        assert_eq!(
+            std::fs::read(dir.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+        assert_eq!(
+            std::fs::read(dir.path.join("Data/enUS/My File.bin")).unwrap(),
+            b"loc data"
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_files_already_mirrored() {
+        let source = TempDir::new("test_mirror_skip_source");
+        let dir = TempDir::new("test_mirror_skip_dir");
+        let manifest = manifest(&source);
+        run_mirror(&manifest, &dir).await;
+
+        let transaction =
+            mirror::plan(&manifest, &dir.path, TransactionOptions::default()).unwrap();
+        assert!(!transaction.has_pending_operations());
+    }
+
+    #[tokio::test]
+    async fn replaces_corrupt_files() {
+        let source = TempDir::new("test_mirror_corrupt_source");
+        let dir = TempDir::new("test_mirror_corrupt_dir");
+        dir.write("Data/A.bin", b"corrupted!");
+
+        let manifest = manifest(&source);
+        let transaction =
+            mirror::plan(&manifest, &dir.path, TransactionOptions::default()).unwrap();
+        assert_eq!(transaction.pending_count(), 2);
+
+        run_mirror(&manifest, &dir).await;
+        assert_eq!(
+            std::fs::read(dir.path.join("Data/A.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[test]
+    fn rewrites_urls_to_the_mirror() {
+        let source = TempDir::new("test_mirror_rewrite_source");
+        let base_url = Url::parse("http://mirror.lan:8080/wow").unwrap();
+
+        let rewritten = mirror::rewrite(&manifest(&source), &base_url).unwrap();
+        let stock = &rewritten.files[0];
+        assert_eq!(
+            stock.get_url(&Provider::Cloudflare).unwrap(),
+            "http://mirror.lan:8080/wow/Data/A.bin"
+        );
+        assert_eq!(
+            stock.get_url(&Provider::None).unwrap(),
+            "http://mirror.lan:8080/wow/Data/A.bin"
+        );
+        assert!(stock.deltas.is_empty());
+
+        let localized = &rewritten.files[1];
+        assert_eq!(
+            localized.get_url(&Provider::None).unwrap(),
+            "http://mirror.lan:8080/wow/Data/enUS/My%20File.bin"
+        );
+        assert_eq!(localized.locale.as_deref(), Some("enUS"));
+        assert!(localized.optional);
+    }
+
+    #[test]
+    fn written_manifest_can_be_loaded() {
+        let source = TempDir::new("test_mirror_written_source");
+        let dir = TempDir::new("test_mirror_written_dir");
+        let base_url = Url::parse("http://mirror.lan/").unwrap();
+
+        let path = mirror::write_manifest(&manifest(&source), &dir.path, &base_url, None).unwrap();
+        let written = Manifest::from_file(&path).unwrap();
+        assert_eq!(written.files.len(), 2);
+        assert_eq!(
+            written.files[0].get_url(&Provider::None).unwrap(),
+            "http://mirror.lan/Data/A.bin"
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_the_written_manifest() {
+        let source = TempDir::new("test_mirror_signed_source");
+        let dir = TempDir::new("test_mirror_signed_dir");
+        let base_url = Url::parse("http://mirror.lan/").unwrap();
+        let key_path = dir.write("signing.key", BASE64.encode([9u8; 32]).as_bytes());
+        let key = signature::load_signing_key(&key_path).unwrap();
+
+        let path =
+            mirror::write_manifest(&manifest(&source), &dir.path, &base_url, Some(&key)).unwrap();
+        let mut trust = TrustedKeys::default();
+        trust.add_key(&signature::public_key(&key)).unwrap();
+        let options = BuildOptions {
+            trust,
+            ..BuildOptions::default()
+        };
+        let written = Manifest::build(&Location::FilePath(path.clone()), &options)
+            .await
+            .unwrap();
+        assert_eq!(written.origin.signature, SignatureStatus::Verified);
+
+        // Writing it again unsigned removes the stale signature
+        mirror::write_manifest(&manifest(&source), &dir.path, &base_url, None).unwrap();
+        assert!(!dir.path.join("manifest.json.sig").exists());
+    }
+}
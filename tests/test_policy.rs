@@ -0,0 +1,159 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{file, hash, manifest_with, url, TempDir, UID};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::pattern::Pattern;
+use rs_manifest_patcher::policy::{ModifiedPolicy, PolicyRules};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+fn manifest(url: &str) -> Manifest {
+    manifest_with(&[file("Data/A.bin", b"patch data", url)])
+}
+
+fn state(hash: &str) -> InstallState {
+    InstallState {
+        uid: UID.to_string(),
+        version: "1.0".to_string(),
+        source: None,
+        provider: Some(Provider::None),
+        channel: None,
+        applied_at: 0,
+        files: BTreeMap::from([("Data/A.bin".to_string(), hash.to_string())]),
+        groups: Default::default(),
+    }
+}
+
+fn options(installed_hash: &str, policy: ModifiedPolicy) -> TransactionOptions {
+    let mut modified_policy = PolicyRules::default();
+    modified_policy.default = policy;
+    TransactionOptions {
+        state: Some(state(installed_hash)),
+        quick_verify: false,
+        modified_policy,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matching() {
+        let addons = Pattern::new("Interface/AddOns").unwrap();
+        assert!(addons.matches("Interface/AddOns/Foo/Foo.toc"));
+        assert!(addons.matches("interface/addons"));
+        assert!(!addons.matches("Interface/FrameXML/Foo.lua"));
+
+        let configs = Pattern::new("*.wtf").unwrap();
+        assert!(configs.matches("WTF/Config.wtf"));
+        assert!(!configs.matches("WTF/Config.txt"));
+
+        let anchored = Pattern::new("/Data/*.MPQ").unwrap();
+        assert!(anchored.matches("Data/patch-A.MPQ"));
+        assert!(!anchored.matches("Backup/Data/patch-A.MPQ"));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let mut rules = PolicyRules::default();
+        rules.add("Interface=keep").unwrap();
+        rules.add("Interface/AddOns/Blizzard_*=backup").unwrap();
+
+        assert_eq!(rules.policy_for("Data/A.MPQ"), ModifiedPolicy::Overwrite);
+        assert_eq!(rules.policy_for("Interface/Foo.xml"), ModifiedPolicy::Keep);
+        assert_eq!(
+            rules.policy_for("Interface/AddOns/Blizzard_Raid/Raid.toc"),
+            ModifiedPolicy::Backup
+        );
+
+        assert!(rules.add("Interface").is_err());
+        assert!(rules.add("Interface=delete").is_err());
+    }
+
+    #[test]
+    fn unmodified_outdated_file_is_not_reported() {
+        let temp = TempDir::new("test_policy_unmodified");
+        temp.write("Data/A.bin", b"old data!!");
+
+        let transaction = Transaction::with_options(
+            manifest("A.bin"),
+            temp.path.clone(),
+            options(&hash(b"old data!!"), ModifiedPolicy::Keep),
+        );
+        assert!(transaction.has_pending_operations());
+        assert!(transaction.generate_report().modified_files.is_empty());
+    }
+
+    #[test]
+    fn keep_policy_leaves_modified_file() {
+        let temp = TempDir::new("test_policy_keep");
+        temp.write("Data/A.bin", b"user data!");
+        let installed = hash(b"old data!!");
+
+        let transaction = Transaction::with_options(
+            manifest("A.bin"),
+            temp.path.clone(),
+            options(&installed, ModifiedPolicy::Keep),
+        );
+        assert!(!transaction.has_pending_operations());
+
+        let report = transaction.generate_report();
+        assert_eq!(report.modified_files.len(), 1);
+        assert_eq!(report.modified_files[0].policy, ModifiedPolicy::Keep);
+
+        // The installed hash is kept so the file is still detected as modified next time
+        let state = transaction.install_state(&Provider::None);
+        assert_eq!(state.files.get("Data/A.bin"), Some(&installed));
+    }
+
+    #[test]
+    fn ask_policy_requires_confirmation() {
+        let temp = TempDir::new("test_policy_ask");
+        temp.write("Data/A.bin", b"user data!");
+
+        let mut transaction = Transaction::with_options(
+            manifest("A.bin"),
+            temp.path.clone(),
+            options(&hash(b"old data!!"), ModifiedPolicy::Ask),
+        );
+        assert_eq!(transaction.modified_to_confirm(), vec!["Data/A.bin"]);
+
+        transaction.confirm_modified("Data/A.bin", false);
+        assert!(transaction.modified_to_confirm().is_empty());
+        assert!(!transaction.has_pending_operations());
+    }
+
+    #[tokio::test]
+    async fn backup_policy_copies_modified_file() {
+        let mirror = TempDir::new("test_policy_backup_mirror");
+        let install = TempDir::new("test_policy_backup_install");
+        let source = mirror.write("A.bin", b"patch data");
+        install.write("Data/A.bin", b"user data!");
+
+        let transaction = Transaction::with_options(
+            manifest(&url(&source)),
+            install.path.clone(),
+            options(&hash(b"old data!!"), ModifiedPolicy::Backup),
+        );
+        assert!(transaction.has_pending_operations());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        let downloaded = std::fs::read(install.path.join("Data/A.bin")).unwrap();
+        assert_eq!(downloaded, b"patch data");
+
+        let backups: Vec<_> = std::fs::read_dir(install.path.join(".patcher/backups"))
+            .unwrap()
+            .collect();
+        assert_eq!(backups.len(), 1);
+        let backup = backups[0].as_ref().unwrap().path().join("Data/A.bin");
+        assert_eq!(std::fs::read(backup).unwrap(), b"user data!");
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{file, manifest_json, Response, TempDir, TestServer};
+use rs_manifest_patcher::manifest::{Location, Manifest, Provider};
+use rs_manifest_patcher::probe::{self, ProviderChoice};
+
+fn manifest_for(server: &TestServer) -> Manifest {
+    let json = format!(
+        r#"
+        {{
+            "Version": "1.0",
+            "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+            "Files": [
+                {{
+                    "Path": "files/A.bin",
+                    "Hash": "b6d81b360a5672d80c27430f39153e2c",
+                    "Size": 1048576,
+                    "Custom": true,
+                    "Urls": {{
+                        "cloudflare": "{}",
+                        "digitalocean": "{}",
+                        "none": "{}"
+                    }}
+                }}
+            ]
+        }}
+        "#,
+        server.url("/fast/A.bin"),
+        server.url("/slow/A.bin"),
+        server.url("/broken/A.bin"),
+    );
+    Manifest::from_json(&json).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn probe_selects_fastest_provider() {
+        let body = vec![0u8; 1024 * 1024];
+        let server = TestServer::start(move |request| {
+            if request.path.starts_with("/fast/") {
+                Response::ranged(request, &body)
+            } else if request.path.starts_with("/slow/") {
+                Response {
+                    delay: Duration::from_millis(300),
+                    ..Response::ranged(request, &body)
+                }
+            } else {
+                Response::not_found()
+            }
+        })
+        .await;
+        let manifest = manifest_for(&server);
+
+        let results = probe::probe_all(&reqwest::Client::new(), &manifest).await;
+        assert_eq!(results.len(), 3);
+
+        let slow = results
+            .iter()
+            .find(|r| r.provider == Provider::DigitalOcean)
+            .unwrap();
+        let measurement = slow.outcome.as_ref().unwrap();
+        assert!(measurement.latency >= Duration::from_millis(300));
+        // Only the requested range is transferred
+        assert_eq!(measurement.bytes, probe::PROBE_SIZE);
+
+        let broken = results
+            .iter()
+            .find(|r| r.provider == Provider::None)
+            .unwrap();
+        assert!(broken.outcome.is_err());
+
+        let fastest = probe::fastest(&results).unwrap();
+        assert_eq!(fastest.provider, Provider::Cloudflare);
+    }
+
+    #[tokio::test]
+    async fn probe_resolves_relative_urls() {
+        let mirror = TempDir::new("test_probe_relative");
+        mirror.write("files/A.bin", &[0u8; 1024]);
+        let json = manifest_json(&[file("files/A.bin", &[0u8; 1024], "files/A.bin")]);
+        let path = mirror.write("manifest.json", json.as_bytes());
+        let mut manifest = Manifest::from_json(&json).unwrap();
+        manifest.origin.location = Some(Location::FilePath(path));
+
+        let result = probe::probe(&reqwest::Client::new(), &manifest, &Provider::None).await;
+        assert_eq!(result.outcome.unwrap().bytes, 1024);
+    }
+
+    #[test]
+    fn fastest_is_none_without_successful_probes() {
+        let results = vec![probe::ProbeResult {
+            provider: Provider::Cloudflare,
+            outcome: Err("unreachable".to_string()),
+        }];
+        assert!(probe::fastest(&results).is_none());
+    }
+
+    #[test]
+    fn remember_provider() {
+        let temp = TempDir::new("test_probe_remember");
+        assert_eq!(probe::load_remembered(&temp.path), None);
+
+        probe::remember(&temp.path, &Provider::Other("mirror-eu".to_string())).unwrap();
+        assert_eq!(
+            probe::load_remembered(&temp.path),
+            Some(Provider::Other("mirror-eu".to_string()))
+        );
+    }
+
+    #[test]
+    fn auto_is_only_a_command_line_choice() {
+        assert_eq!("auto".parse(), Ok(ProviderChoice::Fastest));
+        assert_eq!(
+            "cloudflare".parse(),
+            Ok(ProviderChoice::Fixed(Provider::Cloudflare))
+        );
+        // In a manifest `auto` is an ordinary provider key
+        let provider: Provider = serde_json::from_str(r#""auto""#).unwrap();
+        assert_eq!(provider, Provider::Other("auto".to_string()));
+    }
+}
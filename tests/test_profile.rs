@@ -0,0 +1,92 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::TempDir;
+use rs_manifest_patcher::manifest::Provider;
+use rs_manifest_patcher::profile::{Profile, ProfileRegistry};
+
+fn profile(name: &str) -> Profile {
+    Profile {
+        name: name.to_string(),
+        base_path: PathBuf::from(format!("/games/{name}")),
+        manifest: "http://localhost:8080/manifest.json".to_string(),
+        fallback_manifests: Vec::new(),
+        provider: Provider::Cloudflare,
+        channel: None,
+        groups: Vec::new(),
+        locale: None,
+        trusted_keys: Vec::new(),
+        allow_unsigned: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_registry_is_empty() {
+        let temp = TempDir::new("test_profile_missing");
+        let registry = ProfileRegistry::load(&temp.path.join("profiles.json")).unwrap();
+        assert!(registry.profiles.is_empty());
+    }
+
+    #[test]
+    fn add_save_and_load() {
+        let temp = TempDir::new("test_profile_roundtrip");
+        let path = temp.path.join("nested/profiles.json");
+
+        let mut registry = ProfileRegistry::default();
+        registry.add(profile("production")).unwrap();
+        registry.add(profile("ptr")).unwrap();
+        registry.save(&path).unwrap();
+
+        let loaded = ProfileRegistry::load(&path).unwrap();
+        assert_eq!(loaded.profiles, registry.profiles);
+
+        // Saving again replaces the registry through a temporary file that does not stay behind
+        registry.save(&path).unwrap();
+        let files: Vec<_> = std::fs::read_dir(temp.path.join("nested"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["profiles.json"]);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut registry = ProfileRegistry::default();
+        registry.add(profile("ptr")).unwrap();
+        assert!(registry.add(profile("ptr")).is_err());
+
+        let mut updated = profile("ptr");
+        updated.provider = Provider::DigitalOcean;
+        registry.replace(updated).unwrap();
+        assert_eq!(
+            registry.get("ptr").unwrap().provider,
+            Provider::DigitalOcean
+        );
+    }
+
+    #[test]
+    fn remove_and_select() {
+        let mut registry = ProfileRegistry::default();
+        registry.add(profile("production")).unwrap();
+        registry.add(profile("ptr")).unwrap();
+        registry.add(profile("dev")).unwrap();
+
+        assert_eq!(registry.select(&[]).unwrap().len(), 3);
+        assert!(registry.select(&["missing".to_string()]).is_err());
+
+        registry.remove("ptr").unwrap();
+        assert!(registry.remove("ptr").is_err());
+        let names: Vec<_> = registry
+            .select(&[])
+            .unwrap()
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        assert_eq!(names, vec!["production", "dev"]);
+    }
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{file, hash, manifest_with, url, TempDir};
+use rs_manifest_patcher::manifest::Provider;
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+/// State of a previous installation that had the given files
+fn options(files: &[(&str, &[u8])]) -> TransactionOptions {
+    TransactionOptions {
+        state: Some(InstallState {
+            uid: "2b9f7c51-31f4-4ad4-9a1c-8c1f4f0f1a2e".to_string(),
+            version: "1.0".to_string(),
+            source: None,
+            provider: Some(Provider::None),
+            channel: None,
+            applied_at: 0,
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), hash(contents)))
+                .collect::<BTreeMap<_, _>>(),
+            groups: Default::default(),
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copies_duplicate_file() {
+        let install = TempDir::new("test_reuse_duplicate");
+        install.write("Data/A.bin", b"patch data");
+
+        // B.bin is not available for download, so it must be copied
+        let transaction = Transaction::new(
+            manifest_with(&[
+                file("Data/A.bin", b"patch data", "A.bin"),
+                file("Data/B.bin", b"patch data", "does-not-exist.bin"),
+            ]),
+            install.path.clone(),
+        );
+        let report = transaction.generate_report();
+        assert_eq!(
+            report.missing_files[0].reused_from.as_deref(),
+            Some("Data/A.bin")
+        );
+        assert_eq!(report.total_download_size, 0);
+        assert_eq!(report.total_reused_size, 10);
+
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/B.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[tokio::test]
+    async fn copies_moved_file_from_previous_installation() {
+        let install = TempDir::new("test_reuse_moved");
+        install.write("Data/Old/A.bin", b"patch data");
+
+        let transaction = Transaction::with_options(
+            manifest_with(&[file("Data/New/A.bin", b"patch data", "does-not-exist.bin")]),
+            install.path.clone(),
+            options(&[("Data/Old/A.bin", b"patch data")]),
+        );
+        assert_eq!(
+            transaction.generate_report().missing_files[0]
+                .reused_from
+                .as_deref(),
+            Some("Data/Old/A.bin")
+        );
+
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/New/A.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[tokio::test]
+    async fn changed_source_falls_back_to_download() {
+        let mirror = TempDir::new("test_reuse_changed_mirror");
+        let install = TempDir::new("test_reuse_changed_install");
+        let source = mirror.write("A.bin", b"patch data");
+        install.write("Data/Old/A.bin", b"user data!");
+
+        let transaction = Transaction::with_options(
+            manifest_with(&[file("Data/New/A.bin", b"patch data", &url(&source))]),
+            install.path.clone(),
+            options(&[("Data/Old/A.bin", b"patch data")]),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/New/A.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[tokio::test]
+    async fn copies_outdated_file_before_it_is_updated() {
+        let mirror = TempDir::new("test_reuse_outdated_mirror");
+        let install = TempDir::new("test_reuse_outdated_install");
+        let source = mirror.write("A.bin", b"new data!!");
+        install.write("Data/A.bin", b"old data!!");
+
+        // B.bin gets the old contents of A.bin, which is listed first
+        let transaction = Transaction::new(
+            manifest_with(&[
+                file("Data/A.bin", b"new data!!", &url(&source)),
+                file("Data/B.bin", b"old data!!", "does-not-exist.bin"),
+            ]),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.bin")).unwrap(),
+            b"new data!!"
+        );
+        assert_eq!(
+            std::fs::read(install.path.join("Data/B.bin")).unwrap(),
+            b"old data!!"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hard_links_duplicate_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let install = TempDir::new("test_reuse_hard_link");
+        install.write("Data/A.bin", b"patch data");
+
+        let transaction = Transaction::with_options(
+            manifest_with(&[
+                file("Data/A.bin", b"patch data", "A.bin"),
+                file("Data/B.bin", b"patch data", "does-not-exist.bin"),
+            ]),
+            install.path.clone(),
+            TransactionOptions {
+                hard_link: true,
+                ..Default::default()
+            },
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        let a = std::fs::metadata(install.path.join("Data/A.bin")).unwrap();
+        let b = std::fs::metadata(install.path.join("Data/B.bin")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn updating_linked_file_keeps_other_link() {
+        let mirror = TempDir::new("test_reuse_unlink_mirror");
+        let install = TempDir::new("test_reuse_unlink_install");
+        let source = mirror.write("A.bin", b"new data!!");
+        install.write("Data/B.bin", b"old data!!");
+        std::fs::hard_link(
+            install.path.join("Data/B.bin"),
+            install.path.join("Data/A.bin"),
+        )
+        .unwrap();
+
+        let transaction = Transaction::new(
+            manifest_with(&[
+                file("Data/A.bin", b"new data!!", &url(&source)),
+                file("Data/B.bin", b"old data!!", "B.bin"),
+            ]),
+            install.path.clone(),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(install.path.join("Data/B.bin")).unwrap(),
+            b"old data!!"
+        );
+    }
+}
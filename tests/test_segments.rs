@@ -0,0 +1,183 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{file, manifest_with, Response, TempDir, TestServer};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+fn contents() -> Vec<u8> {
+    (0..1000u32).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn manifest(contents: &[u8], url: &str) -> Manifest {
+    manifest_with(&[file("Data/A.MPQ", contents, url)])
+}
+
+fn options(segment_threshold: u64) -> TransactionOptions {
+    TransactionOptions {
+        segments: 4,
+        segment_threshold,
+        ..Default::default()
+    }
+}
+
+/// Serve `body`, recording the `Range` header of every request
+async fn server(body: Vec<u8>, ranged: bool) -> (TestServer, Arc<Mutex<Vec<Option<String>>>>) {
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let seen = ranges.clone();
+    let server = TestServer::start(move |request| {
+        seen.lock()
+            .unwrap()
+            .push(request.headers.get("range").cloned());
+        if ranged {
+            Response::ranged(request, &body)
+        } else {
+            Response::ok(&body)
+        }
+    })
+    .await;
+    (server, ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn downloads_large_file_in_segments() {
+        let contents = contents();
+        let install = TempDir::new("test_segments_ranges");
+        let (server, ranges) = server(contents.clone(), true).await;
+
+        let transaction = Transaction::with_options(
+            manifest(&contents, &server.url("/A.MPQ")),
+            install.path.clone(),
+            options(0),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.MPQ")).unwrap(),
+            contents
+        );
+        let mut ranges = ranges.lock().unwrap().clone();
+        ranges.sort();
+        assert_eq!(
+            ranges,
+            vec![
+                Some("bytes=0-249".to_string()),
+                Some("bytes=250-499".to_string()),
+                Some("bytes=500-749".to_string()),
+                Some("bytes=750-999".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_combined_progress() {
+        let contents = contents();
+        let install = TempDir::new("test_segments_progress");
+        let (server, _) = server(contents.clone(), true).await;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let transaction = Transaction::with_options(
+            manifest(&contents, &server.url("/A.MPQ")),
+            install.path.clone(),
+            options(0),
+        );
+        transaction
+            .download(
+                &reqwest::Client::new(),
+                move |progress| {
+                    seen.lock().unwrap().push((
+                        progress.current,
+                        progress.file_size,
+                        progress.total_size_downloaded,
+                    ));
+                    Ok(())
+                },
+                Provider::None,
+            )
+            .await
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(*reports.last().unwrap(), (1000, 1000, 1000));
+    }
+
+    #[tokio::test]
+    async fn server_without_range_support_falls_back() {
+        let contents = contents();
+        let install = TempDir::new("test_segments_no_ranges");
+        let (server, ranges) = server(contents.clone(), false).await;
+
+        let transaction = Transaction::with_options(
+            manifest(&contents, &server.url("/A.MPQ")),
+            install.path.clone(),
+            options(0),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.MPQ")).unwrap(),
+            contents
+        );
+        // The segment requests are answered with the whole file, then it is downloaded once
+        assert_eq!(ranges.lock().unwrap().last().unwrap(), &None);
+    }
+
+    #[tokio::test]
+    async fn small_file_uses_one_connection() {
+        let contents = contents();
+        let install = TempDir::new("test_segments_small");
+        let (server, ranges) = server(contents.clone(), true).await;
+
+        let transaction = Transaction::with_options(
+            manifest(&contents, &server.url("/A.MPQ")),
+            install.path.clone(),
+            options(1001),
+        );
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        assert_eq!(*ranges.lock().unwrap(), vec![None]);
+    }
+
+    #[tokio::test]
+    async fn corrupt_segment_fails_hash_check() {
+        let contents = contents();
+        let mut served = contents.clone();
+        served[600] ^= 0xff;
+        let install = TempDir::new("test_segments_corrupt");
+        install.write("Data/A.MPQ", b"previous version");
+        let (server, _) = server(served, true).await;
+
+        let transaction = Transaction::with_options(
+            manifest(&contents, &server.url("/A.MPQ")),
+            install.path.clone(),
+            options(0),
+        );
+        let result = transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::None)
+            .await;
+        assert!(result.is_err());
+        // The failed download neither replaces the installed file nor leaves a partial file
+        assert_eq!(
+            std::fs::read(install.path.join("Data/A.MPQ")).unwrap(),
+            b"previous version"
+        );
+        assert!(!install.path.join("Data/A.MPQ.partial").exists());
+    }
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{file_with, manifest_with, TempDir};
+use ed25519_dalek::SigningKey;
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest, Provider};
+use rs_manifest_patcher::serve::FileServer;
+use rs_manifest_patcher::signature::{self, SignatureStatus, TrustedKeys};
+use rs_manifest_patcher::Transaction;
+use serde_json::json;
+use url::Url;
+
+/// Manifest with a file the installation has, one it has modified and one it lacks
+fn manifest() -> Manifest {
+    let upstream = |path: &str, contents: &[u8], url: &str| {
+        file_with(
+            path,
+            contents,
+            url,
+            json!({ "Urls": { "cloudflare": url } }),
+        )
+    };
+    manifest_with(&[
+        upstream(
+            "Data/My File.bin",
+            b"patch data",
+            "http://upstream.invalid/A.bin",
+        ),
+        upstream("Data/B.bin", b"other data", "http://upstream.invalid/B.bin"),
+        upstream("Data/C.bin", b"third data", "http://upstream.invalid/C.bin"),
+    ])
+}
+
+/// Start a server for `install`, set up by `configure`, and return its base URL
+async fn start_with(install: &TempDir, configure: impl FnOnce(&mut FileServer)) -> String {
+    install.write("Data/My File.bin", b"patch data");
+    install.write("Data/B.bin", b"modified!!");
+    let mut server = FileServer::new(manifest(), install.path.clone()).unwrap();
+    configure(&mut server);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Arc::new(server).serve(listener));
+    format!("http://{addr}")
+}
+
+/// Start a server for `install` and return its base URL
+async fn start(install: &TempDir) -> String {
+    start_with(install, |_| {}).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_only_verified_files() {
+        let install = TempDir::new("test_serve_verified");
+        install.write("Data/My File.bin", b"patch data");
+        install.write("Data/B.bin", b"modified!!");
+
+        let server = FileServer::new(manifest(), install.path.clone()).unwrap();
+        assert_eq!((server.served_count(), server.file_count()), (1, 3));
+
+        let base_url = Url::parse("http://192.168.1.10:8080/").unwrap();
+        let manifest = server.manifest(&base_url).unwrap();
+        assert_eq!(
+            manifest.files[0].get_url(&Provider::Cloudflare).unwrap(),
+            "http://192.168.1.10:8080/Data/My%20File.bin"
+        );
+        // Unverified files keep downloading from the original source
+        assert_eq!(
+            manifest.files[1].get_url(&Provider::Cloudflare).unwrap(),
+            "http://upstream.invalid/B.bin"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_files_and_ranges() {
+        let install = TempDir::new("test_serve_ranges");
+        let url = start(&install).await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{url}/Data/My%20File.bin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"patch data");
+
+        let response = client
+            .get(format!("{url}/Data/My%20File.bin"))
+            .header("Range", "bytes=2-5")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(
+            response.headers()["content-range"].to_str().unwrap(),
+            "bytes 2-5/10"
+        );
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"tch ");
+
+        let response = client
+            .get(format!("{url}/Data/My%20File.bin"))
+            .header("Range", "bytes=-3")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"ata");
+
+        let response = client
+            .get(format!("{url}/Data/My%20File.bin"))
+            .header("Range", "bytes=10-")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 416);
+    }
+
+    #[tokio::test]
+    async fn refuses_unverified_and_unlisted_files() {
+        let install = TempDir::new("test_serve_refuses");
+        let url = start(&install).await;
+        install.write("Data/Secret.txt", b"not in the manifest");
+        let client = reqwest::Client::new();
+
+        for path in [
+            "Data/B.bin",
+            "Data/C.bin",
+            "Data/Secret.txt",
+            "../etc/passwd",
+        ] {
+            let response = client.get(format!("{url}/{path}")).send().await.unwrap();
+            assert_eq!(response.status(), 404, "{path}");
+        }
+        let response = client
+            .post(format!("{url}/Data/My%20File.bin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 405);
+    }
+
+    #[tokio::test]
+    async fn patches_from_the_generated_manifest() {
+        let install = TempDir::new("test_serve_patch_source");
+        let client_install = TempDir::new("test_serve_patch_client");
+        let url = start(&install).await;
+
+        let location = Location::parse(format!("{url}/manifest.json")).unwrap();
+        let mut options = BuildOptions::default();
+        options.trust.allow_unsigned = true;
+        let manifest = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(
+            manifest.files[0].get_url(&Provider::Cloudflare).unwrap(),
+            &format!("{url}/Data/My%20File.bin")
+        );
+
+        // Only the verified file can be fetched from the server
+        let mut manifest = manifest;
+        manifest.files.truncate(1);
+        let transaction = Transaction::new(manifest, client_install.path.clone());
+        transaction
+            .download(&reqwest::Client::new(), |_| Ok(()), Provider::Cloudflare)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(client_install.path.join("Data/My File.bin")).unwrap(),
+            b"patch data"
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_the_generated_manifest() {
+        let install = TempDir::new("test_serve_signed");
+        let key = SigningKey::from_bytes(&[9u8; 32]);
+        let url = start_with(&install, |server| server.signing_key = Some(key.clone())).await;
+
+        let mut trust = TrustedKeys::default();
+        trust.add_key(&signature::public_key(&key)).unwrap();
+        let options = BuildOptions {
+            trust,
+            ..BuildOptions::default()
+        };
+        let location = Location::parse(format!("{url}/manifest.json")).unwrap();
+        let manifest = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(manifest.origin.signature, SignatureStatus::Verified);
+
+        // Without a signing key there is no signature to fetch
+        let unsigned = start(&TempDir::new("test_serve_unsigned")).await;
+        let response = reqwest::get(format!("{unsigned}/manifest.json.sig"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn closes_oversized_and_idle_requests() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let install = TempDir::new("test_serve_limits");
+        let url = start_with(&install, |server| {
+            server.read_timeout = std::time::Duration::from_millis(200)
+        })
+        .await;
+        let addr = url.trim_start_matches("http://").to_string();
+
+        // A header line longer than the limit closes the connection without a response
+        let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        let request = format!(
+            "GET /manifest.json HTTP/1.1\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(16 * 1024)
+        );
+        let _ = stream.write_all(request.as_bytes()).await;
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        assert!(response.is_empty());
+
+        // So does a client that never finishes its request
+        let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        stream
+            .write_all(b"GET /manifest.json HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        let read = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            stream.read_to_end(&mut response),
+        )
+        .await
+        .expect("the server closes the connection");
+        assert!(read.is_ok_and(|read| read == 0));
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use common::{Response, TempDir, TestServer};
+use ed25519_dalek::{Signer, SigningKey};
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest};
+use rs_manifest_patcher::signature::{SignatureStatus, TrustedKeys};
+
+const MANIFEST_JSON: &str = r#"
+{
+    "Version": "1.0",
+    "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+    "Files": []
+}
+"#;
+
+fn signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[7u8; 32])
+}
+
+fn sign(contents: &str) -> String {
+    BASE64.encode(signing_key().sign(contents.as_bytes()).to_bytes())
+}
+
+fn trusting_options() -> BuildOptions {
+    let mut trust = TrustedKeys::default();
+    trust
+        .add_key(&BASE64.encode(signing_key().verifying_key().to_bytes()))
+        .unwrap();
+    BuildOptions {
+        trust,
+        ..BuildOptions::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_invalid_keys() {
+        let mut trust = TrustedKeys::default();
+        assert!(trust.add_key("not base64!").is_err());
+        assert!(trust.add_key(&BASE64.encode([1u8; 16])).is_err());
+    }
+
+    #[tokio::test]
+    async fn signed_file_is_verified() {
+        let temp = TempDir::new("test_signature_signed");
+        let path = temp.write("manifest.json", MANIFEST_JSON.as_bytes());
+        temp.write("manifest.json.sig", sign(MANIFEST_JSON).as_bytes());
+
+        let manifest = Manifest::build(&Location::FilePath(path), &trusting_options())
+            .await
+            .unwrap();
+        assert_eq!(manifest.origin.signature, SignatureStatus::Verified);
+    }
+
+    #[tokio::test]
+    async fn tampered_file_is_rejected() {
+        let temp = TempDir::new("test_signature_tampered");
+        let path = temp.write(
+            "manifest.json",
+            MANIFEST_JSON.replace("1.0", "6.6").as_bytes(),
+        );
+        temp.write("manifest.json.sig", sign(MANIFEST_JSON).as_bytes());
+
+        let result = Manifest::build(&Location::FilePath(path), &trusting_options()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn unsigned_file_requires_override() {
+        let temp = TempDir::new("test_signature_unsigned");
+        let location = Location::FilePath(temp.write("manifest.json", MANIFEST_JSON.as_bytes()));
+
+        let mut options = trusting_options();
+        assert!(Manifest::build(&location, &options).await.is_err());
+
+        options.trust.allow_unsigned = true;
+        let manifest = Manifest::build(&location, &options).await.unwrap();
+        assert_eq!(manifest.origin.signature, SignatureStatus::Unsigned);
+    }
+
+    #[tokio::test]
+    async fn unsigned_file_is_rejected_without_trusted_keys() {
+        let temp = TempDir::new("test_signature_no_keys");
+        let location = Location::FilePath(temp.write("manifest.json", MANIFEST_JSON.as_bytes()));
+
+        let error = Manifest::build(&location, &BuildOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("--allow-unsigned"));
+
+        // A signature cannot be verified without a trusted key either
+        temp.write("manifest.json.sig", sign(MANIFEST_JSON).as_bytes());
+        let error = Manifest::build(&location, &BuildOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("--trusted-key"));
+    }
+
+    #[test]
+    fn keys_file_adds_trusted_keys() {
+        let temp = TempDir::new("test_signature_keys_file");
+        let key = BASE64.encode(signing_key().verifying_key().to_bytes());
+        let path = temp.write(
+            "trusted-keys.txt",
+            format!("# Release key\n\n{key}\n").as_bytes(),
+        );
+
+        let mut trust = TrustedKeys::default();
+        trust.add_keys_file(&temp.path.join("missing.txt")).unwrap();
+        assert!(trust.is_enforced());
+        assert!(trust
+            .verify(MANIFEST_JSON.as_bytes(), Some(&sign(MANIFEST_JSON)))
+            .is_err());
+        trust.add_keys_file(&path).unwrap();
+        assert_eq!(
+            trust.verify(MANIFEST_JSON.as_bytes(), Some(&sign(MANIFEST_JSON))),
+            Ok(SignatureStatus::Verified)
+        );
+    }
+
+    #[tokio::test]
+    async fn signed_url_is_verified() {
+        let signature = sign(MANIFEST_JSON);
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/api/manifest?environment=ptr" => Response::ok(MANIFEST_JSON.as_bytes()),
+            "/api/manifest.sig?environment=ptr" => Response::ok(signature.as_bytes()),
+            _ => Response::not_found(),
+        })
+        .await;
+
+        let location = Location::parse(server.url("/api/manifest?environment=ptr")).unwrap();
+        let manifest = Manifest::build(&location, &trusting_options())
+            .await
+            .unwrap();
+        assert_eq!(manifest.origin.signature, SignatureStatus::Verified);
+    }
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{file, hash, manifest_with, TempDir, UID};
+use rs_manifest_patcher::manifest::{Manifest, Provider};
+use rs_manifest_patcher::state::InstallState;
+use rs_manifest_patcher::transaction::TransactionOptions;
+use rs_manifest_patcher::Transaction;
+
+fn manifest() -> Manifest {
+    manifest_with(&[file(
+        "Data/A.bin",
+        b"patch data",
+        "http://localhost:8080/files/A.bin",
+    )])
+}
+
+fn state(hash: &str) -> InstallState {
+    InstallState {
+        uid: UID.to_string(),
+        version: "1.0".to_string(),
+        source: None,
+        provider: Some(Provider::Cloudflare),
+        channel: None,
+        applied_at: 0,
+        files: BTreeMap::from([("Data/A.bin".to_string(), hash.to_string())]),
+        groups: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let temp = TempDir::new("test_state_roundtrip");
+        assert!(InstallState::load(&temp.path).unwrap().is_none());
+
+        let hash = hash(b"patch data");
+        state(&hash).save(&temp.path).unwrap();
+
+        let loaded = InstallState::load(&temp.path).unwrap().unwrap();
+        assert_eq!(loaded.uid, UID);
+        assert_eq!(loaded.files.get("Data/A.bin"), Some(&hash));
+    }
+
+    #[test]
+    fn install_state_records_manifest_hashes() {
+        let temp = TempDir::new("test_state_record");
+        let hash = hash(b"patch data");
+        temp.write("Data/A.bin", b"patch data");
+
+        let transaction = Transaction::new(manifest(), temp.path.clone());
+        let state = transaction.install_state(&Provider::DigitalOcean);
+        assert_eq!(state.uid, UID);
+        assert_eq!(state.provider, Some(Provider::DigitalOcean));
+        assert_eq!(state.files.get("Data/A.bin"), Some(&hash));
+    }
+
+    #[test]
+    fn quick_verify_skips_hashing_for_applied_manifest() {
+        let temp = TempDir::new("test_state_quick");
+        let hash = hash(b"patch data");
+        // Same size, different contents: only detected by hashing
+        temp.write("Data/A.bin", b"PATCH DATA");
+
+        let quick = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            TransactionOptions {
+                state: Some(state(&hash)),
+                quick_verify: true,
+                ..Default::default()
+            },
+        );
+        assert!(quick.is_applied());
+        assert!(!quick.has_pending_operations());
+        assert!(quick.generate_report().quick_verified);
+
+        let full = Transaction::with_options(
+            manifest(),
+            temp.path.clone(),
+            TransactionOptions {
+                state: Some(state(&hash)),
+                quick_verify: false,
+                ..Default::default()
+            },
+        );
+        assert!(full.has_pending_operations());
+    }
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::TempDir;
+use futures::StreamExt;
+use rs_manifest_patcher::manifest::{BuildOptions, Location, Manifest, Provider};
+use rs_manifest_patcher::signature::TrustedKeys;
+use rs_manifest_patcher::transport::{Source, TransportError};
+use rs_manifest_patcher::Transaction;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_absolute_urls() {
+        assert!(matches!(
+            Source::resolve("https://cdn.example/files/A.bin", None),
+            Ok(Source::Http(_))
+        ));
+        assert_eq!(
+            Source::resolve("file:///srv/files/A.bin", None),
+            Ok(Source::File(PathBuf::from("/srv/files/A.bin")))
+        );
+        assert!(Source::resolve("ftp://cdn.example/files/A.bin", None).is_err());
+    }
+
+    #[test]
+    fn resolve_relative_to_manifest() {
+        let url_base =
+            Location::parse("http://localhost:8080/api/manifest.json".to_string()).unwrap();
+        assert_eq!(
+            Source::resolve("files/A.bin", Some(&url_base))
+                .unwrap()
+                .to_string(),
+            "http://localhost:8080/api/files/A.bin"
+        );
+
+        let file_base = Location::FilePath(PathBuf::from("/srv/mirror/manifest.json"));
+        assert_eq!(
+            Source::resolve("files/A.bin", Some(&file_base)),
+            Ok(Source::File(PathBuf::from("/srv/mirror/files/A.bin")))
+        );
+
+        assert_eq!(
+            Source::resolve("files/A.bin", Some(&Location::Stdin)),
+            Ok(Source::File(PathBuf::from("files/A.bin")))
+        );
+    }
+
+    #[test]
+    fn resolve_windows_drive_as_path() {
+        assert_eq!(
+            Source::resolve("C:/mirror/A.bin", None),
+            Ok(Source::File(PathBuf::from("C:/mirror/A.bin")))
+        );
+    }
+
+    #[tokio::test]
+    async fn open_local_file() {
+        let temp = TempDir::new("test_transport_open");
+        let path = temp.write("A.bin", b"patch data");
+
+        let stream = Source::File(path)
+            .open(&reqwest::Client::new())
+            .await
+            .unwrap();
+        let chunks: Vec<_> = stream.map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(chunks.concat(), b"patch data");
+
+        let missing = Source::File(temp.path.join("missing.bin"))
+            .open(&reqwest::Client::new())
+            .await;
+        assert!(matches!(missing, Err(TransportError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn download_relative_to_manifest_file() {
+        let mirror = TempDir::new("test_transport_mirror");
+        let install = TempDir::new("test_transport_install");
+        mirror.write("files/A.bin", b"patch data");
+        let manifest_path = mirror.write(
+            "manifest.json",
+            br#"{
+                "Version": "1.0",
+                "Uid": "5a63cd8c-956c-48a0-95ae-7e41d1e73182",
+                "Files": [
+                    {
+                        "Path": "Data/A.bin",
+                        "Hash": "0f6bab340b8ab0f005e3e736ae5d3720",
+                        "Size": 10,
+                        "Custom": true,
+                        "Urls": { "none": "files/A.bin" }
+                    }
+                ]
+            }"#,
+        );
+
+        let mut trust = TrustedKeys::default();
+        trust.allow_unsigned = true;
+        let options = BuildOptions {
+            trust,
+            ..BuildOptions::default()
+        };
+        let manifest = Manifest::build(&Location::FilePath(manifest_path), &options)
+            .await
+            .unwrap();
+
+        let transaction = Transaction::new(manifest, install.path.clone());
+        transaction
+            .download(&options.client, |_| Ok(()), Provider::None)
+            .await
+            .unwrap();
+
+        let downloaded = std::fs::read(install.path.join("Data/A.bin")).unwrap();
+        assert_eq!(downloaded, b"patch data");
+    }
+}